@@ -3,6 +3,8 @@ use bevy::prelude::*;
 #[derive(Resource, Default)]
 pub struct MusicManager {
     pub current: Option<Entity>,
+    /// Path of the track that is playing, relative to `audio/music/`.
+    pub track: Option<String>,
 }
 
 #[derive(Component)]
@@ -21,6 +23,7 @@ pub fn play_music(
 
     let source: Handle<AudioSource> =
         asset_server.load(format!("audio/music/{}", path));
+    manager.track = Some(path);
 
     let entity = commands.spawn((
         AudioPlayer::new(source),
@@ -38,6 +41,42 @@ pub fn stop_music(
     if let Some(entity) = manager.current.take() {
         commands.entity(entity).despawn();
     }
+    manager.track = None;
+}
+
+/// The voice clip of the line on screen, cut off when the next line starts.
+#[derive(Resource, Default)]
+pub struct VoiceManager {
+    pub current: Option<Entity>,
+}
+
+pub fn play_voice(
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    manager: &mut ResMut<VoiceManager>,
+    path: &str,
+) {
+    stop_voice(commands, manager);
+
+    let source: Handle<AudioSource> =
+        asset_server.load(format!("audio/voice/{}", path));
+
+    let entity = commands.spawn((
+        AudioPlayer::new(source),
+        PlaybackSettings::DESPAWN,
+    )).id();
+
+    manager.current = Some(entity);
+}
+
+pub fn stop_voice(
+    commands: &mut Commands,
+    manager: &mut ResMut<VoiceManager>,
+) {
+    if let Some(entity) = manager.current.take() {
+        // The clip may have finished and despawned itself already
+        commands.entity(entity).try_despawn();
+    }
 }
 
 pub fn play_sfx(
@@ -10,28 +10,69 @@ mod audio;
 fn main() {
     App::new()
         .add_plugins(DefaultPlugins)
+        .init_asset::<script::asset::VnScript>()
+        .init_asset_loader::<script::asset::VnScriptLoader>()
+        .init_resource::<script::asset::ActiveScript>()
         .init_resource::<scene::background::BackgroundManager>()
         .init_resource::<scene::characters::CharacterManager>()
+        .init_resource::<scene::characters::CharacterDefs>()
         .init_resource::<audio::MusicManager>()
+        .init_resource::<audio::VoiceManager>()
         .init_resource::<vars::store::VarStore>()
         .init_resource::<script::runner::ScriptRunner>()
+        .init_resource::<script::skip::SeenLines>()
+        .init_resource::<script::skip::SkipMode>()
+        .init_resource::<script::auto::AutoForward>()
         .init_resource::<ui::dialogue::DialogueState>()
+        .init_resource::<ui::dialogue::Typewriter>()
+        .init_resource::<ui::dialogue::DialogueFonts>()
         .init_resource::<ui::choices::ChoiceRequest>()
+        .init_resource::<ui::history::DialogueHistory>()
+        .init_resource::<ui::history::HistoryScreen>()
+        .init_resource::<save::migration::MigrationRegistry>()
+        .init_resource::<save::metadata::PlayTime>()
+        .init_resource::<save::autosave::Autosaves>()
+        .init_resource::<save::rollback::Rollback>()
+        .add_message::<save::save_system::SaveRequest>()
+        .add_message::<save::save_system::LoadRequest>()
         .add_systems(
             Startup,
             (
                 scene::loader::load_test_scene,
                 ui::dialogue::setup_dialogue_ui,
+                vars::persistent::load_persistent_vars,
+                script::skip::load_seen_lines,
             ),
         )
         .add_systems(
             Update,
             (
-                script::runner::script_runner_system,
+                // Input first, then the runner, then the UI, so a whole batch
+                // of instructions and its results land in the same frame
+                script::asset::apply_script_changes,
+                ui::history::toggle_history,
                 script::runner::advance_dialogue,
-                ui::dialogue::update_dialogue_text,
-                ui::choices::choice_ui_system,
+                script::skip::skip_system,
+                script::auto::auto_forward_system,
                 ui::choices::choice_click_system,
+                save::save_system::slot_hotkeys,
+                save::save_system::save_load_system,
+                save::rollback::rollback_system,
+                script::runner::script_runner_system,
+                ui::dialogue::start_dialogue_line,
+                ui::dialogue::reveal_dialogue_text,
+                ui::choices::choice_ui_system,
+                ui::history::history_ui_system,
+                ui::history::scroll_history,
+            )
+                .chain(),
+        )
+        .add_systems(
+            Update,
+            (
+                save::metadata::track_play_time,
+                vars::persistent::save_persistent_vars,
+                script::skip::save_seen_lines,
             ),
         )
         .run();
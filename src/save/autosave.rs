@@ -0,0 +1,46 @@
+use bevy::prelude::*;
+
+use crate::save::metadata::read_meta;
+use crate::save::save_system::{SaveData, Slot};
+
+/// Default for `Autosaves::slots`.
+pub const DEFAULT_AUTOSAVE_SLOTS: u32 = 3;
+
+/// Autosaves taken by the script runner, written to a rotating set of slots
+/// so a bad autosave never replaces the only one.
+#[derive(Resource)]
+pub struct Autosaves {
+    /// Number of autosave slots to rotate through.
+    pub slots: u32,
+    /// The latest snapshot not yet written. Several autosave points in one
+    /// frame keep only the last.
+    pub pending: Option<SaveData>,
+    last: Option<u32>,
+}
+
+impl Default for Autosaves {
+    fn default() -> Self {
+        Self {
+            slots: DEFAULT_AUTOSAVE_SLOTS,
+            pending: None,
+            last: None,
+        }
+    }
+}
+
+impl Autosaves {
+    /// Picks the slot for the next autosave: the one after the last written,
+    /// or on the first autosave of a session, an empty or the oldest slot.
+    pub fn next_slot(&mut self) -> Slot {
+        let slots = self.slots.max(1);
+        let index = match self.last {
+            Some(last) => last % slots + 1,
+            None => (1..=slots)
+                .min_by_key(|&index| read_meta(Slot::Auto(index)).map_or(0, |meta| meta.saved_at))
+                .unwrap_or(1),
+        };
+
+        self.last = Some(index);
+        Slot::Auto(index)
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+use bevy::tasks::IoTaskPool;
+use image::{imageops, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+use crate::save::save_system::{SaveData, SaveError, Slot, SAVE_DIR};
+use crate::scene::background::background_path;
+use crate::scene::characters::{character_transform, CharacterDefs};
+
+/// Where the asset server reads from, for building thumbnails outside it.
+pub const ASSET_DIR: &str = "assets";
+
+pub const THUMBNAIL_WIDTH: u32 = 192;
+pub const THUMBNAIL_HEIGHT: u32 = 108;
+
+/// Size of the world the camera shows at the default window size; thumbnail
+/// coordinates are scaled down from it.
+const VIEW_WIDTH: f32 = 1280.0;
+
+/// Stand-in size, in world units, for a character whose sprite is missing.
+const PLACEHOLDER_SPRITE: (f32, f32) = (300.0, 600.0);
+
+/// Total time spent in the game, carried across saves.
+#[derive(Resource, Default)]
+pub struct PlayTime {
+    pub seconds: f64,
+}
+
+/// What a save screen shows for a slot, stored next to the save so it can be
+/// listed without loading the whole game.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlotMeta {
+    /// Seconds since the Unix epoch when the slot was written.
+    pub saved_at: u64,
+    /// Play time in seconds at the moment of saving.
+    pub play_time: f64,
+    /// Label the game was in, `None` before the first one.
+    pub label: Option<String>,
+    pub speaker: Option<String>,
+    pub line: Option<String>,
+}
+
+impl SlotMeta {
+    pub fn from_save(data: &SaveData, saved_at: u64) -> Self {
+        Self {
+            saved_at,
+            play_time: data.play_time,
+            label: data.runner.position.label.clone(),
+            speaker: data.dialogue.speaker.clone(),
+            line: data.dialogue.current_line.clone(),
+        }
+    }
+}
+
+pub fn meta_path(slot: Slot) -> PathBuf {
+    PathBuf::from(SAVE_DIR).join(format!("{}.meta.ron", slot.file_stem()))
+}
+
+pub fn thumbnail_path(slot: Slot) -> PathBuf {
+    PathBuf::from(SAVE_DIR).join(format!("{}.png", slot.file_stem()))
+}
+
+/// Writes the metadata for a slot that has just been saved, and starts
+/// drawing its thumbnail.
+pub fn write_meta(slot: Slot, data: &SaveData, defs: &CharacterDefs) -> Result<(), SaveError> {
+    let saved_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+
+    let text = ron::ser::to_string_pretty(
+        &SlotMeta::from_save(data, saved_at),
+        ron::ser::PrettyConfig::default(),
+    )
+    .map_err(SaveError::Serialize)?;
+
+    fs::create_dir_all(SAVE_DIR)?;
+    fs::write(meta_path(slot), text)?;
+
+    // Decoding and scaling the scene's images takes long enough to drop
+    // frames, so the thumbnail is drawn off the main thread
+    let (data, defs) = (data.clone(), defs.clone());
+    IoTaskPool::get()
+        .spawn(async move {
+            let result = compose_thumbnail(Path::new(ASSET_DIR), &data, &defs)
+                .save(thumbnail_path(slot))
+                .map_err(SaveError::Thumbnail);
+
+            if let Err(err) = result {
+                error!("failed to save {}: {}", slot, err);
+            }
+        })
+        .detach();
+
+    Ok(())
+}
+
+/// Reads a slot's metadata without touching the save itself.
+pub fn read_meta(slot: Slot) -> Result<SlotMeta, SaveError> {
+    let text = fs::read_to_string(meta_path(slot))?;
+    ron::from_str(&text).map_err(SaveError::Deserialize)
+}
+
+/// Draws a small picture of the saved scene from the background and
+/// character images on disk. Anything that can't be read is drawn as a flat
+/// color picked from its path, so every scene still gets a distinct
+/// thumbnail. Sprites are found the way `show` finds them, through `defs`.
+pub fn compose_thumbnail(asset_dir: &Path, data: &SaveData, defs: &CharacterDefs) -> RgbaImage {
+    let mut thumbnail = match &data.background {
+        Some(path) => match image::open(asset_dir.join(background_path(path))) {
+            Ok(image) => imageops::resize(
+                &image.to_rgba8(),
+                THUMBNAIL_WIDTH,
+                THUMBNAIL_HEIGHT,
+                imageops::FilterType::Triangle,
+            ),
+            Err(_) => RgbaImage::from_pixel(
+                THUMBNAIL_WIDTH,
+                THUMBNAIL_HEIGHT,
+                placeholder_color(&background_path(path)),
+            ),
+        },
+        None => RgbaImage::from_pixel(THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT, Rgba([0, 0, 0, 255])),
+    };
+
+    let scale = THUMBNAIL_WIDTH as f32 / VIEW_WIDTH;
+
+    let mut characters: Vec<_> = data
+        .characters
+        .iter()
+        .map(|character| (character, character_transform(&character.params)))
+        .collect();
+    characters.sort_by(|(_, a), (_, b)| a.translation.z.total_cmp(&b.translation.z));
+
+    for (character, transform) in characters {
+        let path = defs.sprite_path(&character.name, &character.expression);
+        let size = |(width, height): (f32, f32)| {
+            (
+                ((width * transform.scale.x * scale).round() as u32).max(1),
+                ((height * transform.scale.y * scale).round() as u32).max(1),
+            )
+        };
+
+        let sprite = match image::open(asset_dir.join(&path)) {
+            Ok(image) => {
+                let (width, height) = size((image.width() as f32, image.height() as f32));
+                imageops::resize(&image.to_rgba8(), width, height, imageops::FilterType::Triangle)
+            }
+            Err(_) => {
+                let (width, height) = size(PLACEHOLDER_SPRITE);
+                RgbaImage::from_pixel(width, height, placeholder_color(&path))
+            }
+        };
+
+        // World space is centered with y up; image space starts top-left
+        let x = THUMBNAIL_WIDTH as f32 / 2.0 + transform.translation.x * scale
+            - sprite.width() as f32 / 2.0;
+        let y = THUMBNAIL_HEIGHT as f32 / 2.0 - transform.translation.y * scale
+            - sprite.height() as f32 / 2.0;
+
+        imageops::overlay(&mut thumbnail, &sprite, x.round() as i64, y.round() as i64);
+    }
+
+    thumbnail
+}
+
+fn placeholder_color(path: &str) -> Rgba<u8> {
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    let [r, g, b, ..] = hasher.finish().to_le_bytes();
+    Rgba([r, g, b, 255])
+}
+
+pub fn track_play_time(time: Res<Time>, mut play_time: ResMut<PlayTime>) {
+    play_time.seconds += time.delta_secs_f64();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::save::migration::MigrationRegistry;
+    use crate::save::save_system::parse_save;
+    use crate::scene::characters::{sprite_path, CharacterDef};
+
+    fn fixture() -> SaveData {
+        parse_save(include_str!("../../tests/fixtures/save_v1.ron"), &MigrationRegistry::default())
+            .unwrap()
+    }
+
+    #[test]
+    fn meta_comes_from_the_save() {
+        let meta = SlotMeta::from_save(&fixture(), 1_700_000_000);
+
+        assert_eq!(meta.saved_at, 1_700_000_000);
+        assert_eq!(meta.label.as_deref(), Some("chapter1"));
+        assert_eq!(meta.speaker.as_deref(), Some("alice"));
+        assert_eq!(meta.line.as_deref(), Some("Good morning!"));
+    }
+
+    /// Thumbnail of the fixture with every image missing.
+    fn placeholder_thumbnail(defs: &CharacterDefs) -> RgbaImage {
+        compose_thumbnail(Path::new("does-not-exist"), &fixture(), defs)
+    }
+
+    /// Color of the fixture's alice, who stands at the `left` preset, (-400,
+    /// -100) in world space.
+    fn alice_color(thumbnail: &RgbaImage) -> Rgba<u8> {
+        let x = (THUMBNAIL_WIDTH as f32 / 2.0 - 400.0 * THUMBNAIL_WIDTH as f32 / VIEW_WIDTH) as u32;
+        let y = (THUMBNAIL_HEIGHT as f32 / 2.0 + 100.0 * THUMBNAIL_WIDTH as f32 / VIEW_WIDTH) as u32;
+        *thumbnail.get_pixel(x, y)
+    }
+
+    #[test]
+    fn thumbnail_uses_placeholders_for_missing_images() {
+        let thumbnail = placeholder_thumbnail(&CharacterDefs::default());
+
+        assert_eq!(thumbnail.dimensions(), (THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT));
+
+        let background = placeholder_color(&background_path("kitchen.png"));
+        assert_eq!(*thumbnail.get_pixel(0, 0), background);
+        assert_eq!(alice_color(&thumbnail), placeholder_color(&sprite_path("alice", "happy")));
+    }
+
+    #[test]
+    fn thumbnail_looks_in_the_defined_sprite_folder() {
+        let mut defs = CharacterDefs::default();
+        defs.defs.insert(
+            "alice".to_string(),
+            CharacterDef { sprites: Some("alice_school".to_string()), ..default() },
+        );
+        let thumbnail = placeholder_thumbnail(&defs);
+
+        let sprite = sprite_path("alice_school", "happy");
+        assert_eq!(alice_color(&thumbnail), placeholder_color(&sprite));
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::save::save_system::{SaveData, SaveError};
+use crate::script::runner::ScriptPosition;
+
+/// Format version written into every new save. Bump it together with a
+/// migration from the previous version whenever saved data changes meaning.
+pub const SAVE_VERSION: u32 = 1;
+
+/// Upgrades the text of a save by one version. Each step reads the layout of
+/// the version it upgrades from, so fields can be renamed, removed or
+/// retyped; `convert` does the parsing and writing for typed steps.
+pub type MigrationFn = fn(&str) -> Result<String, SaveError>;
+
+/// Migrations applied to older saves on load, keyed by the version they
+/// upgrade from.
+#[derive(Resource)]
+pub struct MigrationRegistry {
+    pub current: u32,
+    pub migrations: HashMap<u32, MigrationFn>,
+}
+
+impl Default for MigrationRegistry {
+    fn default() -> Self {
+        Self {
+            current: SAVE_VERSION,
+            migrations: HashMap::new(),
+        }
+    }
+}
+
+/// Just enough of a save to tell which layout the rest is in.
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+impl MigrationRegistry {
+    /// Registers the migration from `from` to `from + 1`. The engine has no
+    /// migrations of its own yet; games register theirs here.
+    #[allow(dead_code)]
+    pub fn register(&mut self, from: u32, migration: MigrationFn) -> &mut Self {
+        self.migrations.insert(from, migration);
+        self
+    }
+
+    /// Brings a save up to the current version one step at a time, then
+    /// parses it.
+    pub fn migrate(&self, text: &str) -> Result<SaveData, SaveError> {
+        let Header { version } = ron::from_str(text).map_err(SaveError::Deserialize)?;
+        if version > self.current {
+            return Err(SaveError::UnsupportedVersion(version));
+        }
+
+        let mut text = text.to_string();
+        for from in version..self.current {
+            let migration = self
+                .migrations
+                .get(&from)
+                .ok_or(SaveError::MissingMigration(from))?;
+
+            text = migration(&text)?;
+        }
+
+        let mut data: SaveData = ron::from_str(&text).map_err(SaveError::Deserialize)?;
+        data.version = self.current;
+        Ok(data)
+    }
+}
+
+/// Runs a migration written against typed layouts: parses the save as `Old`,
+/// converts it, and writes the result back out.
+#[allow(dead_code)]
+pub fn convert<Old, New>(text: &str, step: impl FnOnce(Old) -> New) -> Result<String, SaveError>
+where
+    Old: DeserializeOwned,
+    New: Serialize,
+{
+    let old = ron::from_str(text).map_err(SaveError::Deserialize)?;
+    ron::to_string(&step(old)).map_err(SaveError::Serialize)
+}
+
+/// Moves a variable to a new name, keeping its value.
+#[allow(dead_code)]
+pub fn rename_var(data: &mut SaveData, from: &str, to: &str) {
+    if let Some(value) = data.vars.remove(from) {
+        data.vars.insert(to.to_string(), value);
+    }
+}
+
+/// Points every saved position that was anchored to label `from` at `to`.
+#[allow(dead_code)]
+pub fn remap_label(data: &mut SaveData, from: &str, to: &str) {
+    let positions = std::iter::once(&mut data.runner.position).chain(&mut data.runner.call_stack);
+
+    for position in positions {
+        if let ScriptPosition { label: Some(label), .. } = position
+            && label == from
+        {
+            *label = to.to_string();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::save::save_system::parse_save;
+    use crate::vars::store::Value;
+
+    const SAVE_V1: &str = include_str!("../../tests/fixtures/save_v1.ron");
+
+    fn rename_gold(text: &str) -> Result<String, SaveError> {
+        convert(text, |mut data: SaveData| {
+            rename_var(&mut data, "gold", "coins");
+            data
+        })
+    }
+
+    fn split_chapter_one(text: &str) -> Result<String, SaveError> {
+        convert(text, |mut data: SaveData| {
+            remap_label(&mut data, "chapter1", "chapter1_morning");
+            if let Some(Value::Int(coins)) = data.vars.get_mut("coins") {
+                *coins *= 10;
+            }
+            data
+        })
+    }
+
+    /// A game that has shipped two formats of its own on top of the engine's.
+    fn game_registry() -> MigrationRegistry {
+        let mut registry = MigrationRegistry { current: SAVE_VERSION + 2, ..default() };
+        registry
+            .register(SAVE_VERSION, rename_gold)
+            .register(SAVE_VERSION + 1, split_chapter_one);
+        registry
+    }
+
+    #[test]
+    fn loads_current_version_unchanged() {
+        let data = parse_save(SAVE_V1, &MigrationRegistry::default()).unwrap();
+
+        assert_eq!(data.version, SAVE_VERSION);
+        assert_eq!(data.vars.get("gold"), Some(&Value::Int(3)));
+        assert_eq!(data.runner.position.label.as_deref(), Some("chapter1"));
+        assert_eq!(data.runner.position.offset, 4);
+
+        let text = ron::to_string(&data).unwrap();
+        let reloaded = parse_save(&text, &MigrationRegistry::default()).unwrap();
+        assert_eq!(reloaded.vars, data.vars);
+        assert_eq!(reloaded.runner.position, data.runner.position);
+    }
+
+    #[test]
+    fn game_migrations_run_in_order() {
+        let data = parse_save(SAVE_V1, &game_registry()).unwrap();
+
+        assert_eq!(data.version, SAVE_VERSION + 2);
+        assert_eq!(data.vars.get("gold"), None);
+        assert_eq!(data.vars.get("coins"), Some(&Value::Int(30)));
+        assert_eq!(data.runner.position.label.as_deref(), Some("chapter1_morning"));
+        assert_eq!(data.runner.position.offset, 4);
+        assert_eq!(data.runner.call_stack[0].label.as_deref(), Some("chapter1_morning"));
+        assert_eq!(data.dialogue.current_line.as_deref(), Some("Good morning!"));
+    }
+
+    #[test]
+    fn rejects_saves_from_newer_versions() {
+        let registry = MigrationRegistry { current: 0, ..default() };
+
+        assert!(matches!(
+            parse_save(SAVE_V1, &registry),
+            Err(SaveError::UnsupportedVersion(1)),
+        ));
+    }
+
+    #[test]
+    fn reports_gap_in_migrations() {
+        let mut registry = MigrationRegistry { current: SAVE_VERSION + 2, ..default() };
+        registry.register(SAVE_VERSION, rename_gold);
+
+        assert!(matches!(
+            parse_save(SAVE_V1, &registry),
+            Err(SaveError::MissingMigration(m)) if m == SAVE_VERSION + 1,
+        ));
+    }
+}
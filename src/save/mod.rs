@@ -0,0 +1,5 @@
+pub mod save_system;
+pub mod migration;
+pub mod metadata;
+pub mod autosave;
+pub mod rollback;
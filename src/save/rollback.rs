@@ -0,0 +1,126 @@
+use std::collections::VecDeque;
+
+use bevy::input::mouse::AccumulatedMouseScroll;
+use bevy::prelude::*;
+
+use crate::save::save_system::{restore, SaveData};
+use crate::script::runner::{ScriptContext, ScriptRunner};
+use crate::ui::choices::ChoiceRoot;
+use crate::ui::history::HistoryScreen;
+
+/// Default for `Rollback::depth`.
+pub const DEFAULT_ROLLBACK_DEPTH: usize = 50;
+
+/// Snapshots of the game at each `say` and `choice` the player has seen, so
+/// they can step back through them and forward again.
+#[derive(Resource)]
+pub struct Rollback {
+    /// How many steps back the player can go.
+    pub depth: usize,
+    /// Snapshots up to and including the line on screen, oldest first.
+    history: VecDeque<SaveData>,
+    /// Snapshots rolled back past, the nearest last.
+    future: Vec<SaveData>,
+}
+
+impl Default for Rollback {
+    fn default() -> Self {
+        Self {
+            depth: DEFAULT_ROLLBACK_DEPTH,
+            history: VecDeque::new(),
+            future: Vec::new(),
+        }
+    }
+}
+
+impl Rollback {
+    /// Records the line or choice the runner has just stopped at.
+    pub fn record(&mut self, snapshot: SaveData) {
+        // Playing on from a rolled back point: the way forward survives only
+        // if the game reached the same place in the same state
+        match self.future.pop() {
+            Some(next) if same_point(&next, &snapshot) => {}
+            Some(_) => self.future.clear(),
+            None => {}
+        }
+
+        self.history.push_back(snapshot);
+        while self.history.len() > self.depth + 1 {
+            self.history.pop_front();
+        }
+    }
+
+    /// Steps back one snapshot, returning the one to restore.
+    pub fn back(&mut self) -> Option<SaveData> {
+        if self.history.len() < 2 {
+            return None;
+        }
+
+        self.future.extend(self.history.pop_back());
+        self.history.back().cloned()
+    }
+
+    /// Steps forward again over a snapshot rolled back past.
+    pub fn forward(&mut self) -> Option<SaveData> {
+        let snapshot = self.future.pop()?;
+        self.history.push_back(snapshot.clone());
+        Some(snapshot)
+    }
+
+    /// Forgets everything, when the game on screen is replaced.
+    pub fn clear(&mut self) {
+        self.history.clear();
+        self.future.clear();
+    }
+}
+
+fn same_point(a: &SaveData, b: &SaveData) -> bool {
+    a.runner.position == b.runner.position
+        && a.runner.call_stack == b.runner.call_stack
+        && a.vars == b.vars
+}
+
+/// Mouse wheel up or Page Up rolls back a line; wheel down or Page Down
+/// rolls forward again.
+pub fn rollback_system(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    scroll: Res<AccumulatedMouseScroll>,
+    history: Res<HistoryScreen>,
+    choice_roots: Query<Entity, With<ChoiceRoot>>,
+    mut runner: ResMut<ScriptRunner>,
+    mut ctx: ScriptContext,
+) {
+    // The wheel scrolls the history while it is open
+    if history.open {
+        return;
+    }
+
+    let snapshot = if scroll.delta.y > 0.0 || keyboard.just_pressed(KeyCode::PageUp) {
+        ctx.rollback.back()
+    } else if scroll.delta.y < 0.0 || keyboard.just_pressed(KeyCode::PageDown) {
+        ctx.rollback.forward()
+    } else {
+        None
+    };
+
+    let Some(snapshot) = snapshot else {
+        return;
+    };
+
+    // Rolling back rewinds the story, not the clock
+    let play_time = ctx.play_time.seconds;
+    let result = restore(snapshot, &mut runner, &mut ctx);
+    ctx.play_time.seconds = play_time;
+
+    match result {
+        Ok(()) => {
+            for root in &choice_roots {
+                ctx.commands.entity(root).despawn();
+            }
+        }
+        Err(err) => {
+            error!("failed to roll back: {}", err);
+            ctx.rollback.clear();
+        }
+    }
+}
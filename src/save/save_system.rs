@@ -0,0 +1,399 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::audio::{play_music, stop_music, stop_voice};
+use crate::save::metadata::write_meta;
+use crate::save::migration::{MigrationRegistry, SAVE_VERSION};
+use crate::scene::background::{clear_background, set_background_image};
+use crate::scene::characters::{hide_character, show_character, CharacterDefs, TransformParams};
+use crate::script::runner::{Instruction, ScriptContext, ScriptPosition, ScriptRunner};
+use crate::ui::choices::ChoiceRoot;
+use crate::ui::history::HistoryEntry;
+use crate::vars::store::Value;
+
+/// Directory that holds the numbered slot files.
+pub const SAVE_DIR: &str = "saves";
+
+/// Everything needed to put the game back exactly where it was.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveData {
+    /// Format version, see `migration::SAVE_VERSION`.
+    pub version: u32,
+    pub runner: RunnerSave,
+    pub vars: HashMap<String, Value>,
+    pub dialogue: DialogueSave,
+    pub background: Option<String>,
+    pub characters: Vec<CharacterSave>,
+    pub music: Option<String>,
+    /// Seconds played before this save; older saves start from zero.
+    #[serde(default)]
+    pub play_time: f64,
+    /// Dialogue history, oldest first.
+    #[serde(default)]
+    pub history: Vec<HistoryEntry>,
+}
+
+/// Runner state with every instruction pointer stored as a label-relative
+/// position, so saves keep working after the script is patched.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunnerSave {
+    /// The line being waited on, or the next instruction to run when not
+    /// waiting; see `ScriptRunner::resume_position`.
+    pub position: ScriptPosition,
+    pub waiting: bool,
+    pub call_stack: Vec<ScriptPosition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DialogueSave {
+    pub speaker: Option<String>,
+    /// ID of the defined character speaking, if any.
+    #[serde(default)]
+    pub character: Option<String>,
+    pub current_line: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CharacterSave {
+    pub name: String,
+    pub expression: String,
+    pub params: TransformParams,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Serialize(ron::Error),
+    Deserialize(ron::error::SpannedError),
+    /// The save was written by a newer version of the game.
+    UnsupportedVersion(u32),
+    /// No migration is registered from this version to the next.
+    MissingMigration(u32),
+    /// The save refers to a label the current script no longer has, or
+    /// points past its end.
+    InvalidPosition(ScriptPosition),
+    /// The slot thumbnail could not be written.
+    Thumbnail(image::ImageError),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(err) => write!(f, "{}", err),
+            SaveError::Serialize(err) => write!(f, "failed to write save: {}", err),
+            SaveError::Deserialize(err) => write!(f, "corrupt save: {}", err),
+            SaveError::UnsupportedVersion(version) => {
+                write!(f, "save version {} is newer than this game supports", version)
+            }
+            SaveError::MissingMigration(version) => {
+                write!(f, "no migration from save version {}", version)
+            }
+            SaveError::InvalidPosition(ScriptPosition { label: Some(label), .. }) => {
+                write!(f, "save refers to missing label `{}`", label)
+            }
+            SaveError::InvalidPosition(ScriptPosition { label: None, offset }) => {
+                write!(f, "save position {} is outside the current script", offset)
+            }
+            SaveError::Thumbnail(err) => write!(f, "failed to write thumbnail: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<std::io::Error> for SaveError {
+    fn from(err: std::io::Error) -> Self {
+        SaveError::Io(err)
+    }
+}
+
+/// A place a game can be saved to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    /// A slot the player picked, numbered from 1.
+    Manual(u32),
+    /// The single slot behind quick save and quick load.
+    Quick,
+    /// One of the rotating autosave slots, numbered from 1.
+    Auto(u32),
+}
+
+impl Slot {
+    /// File name, without extension, of everything stored for this slot.
+    pub fn file_stem(&self) -> String {
+        match self {
+            Slot::Manual(index) => format!("slot_{}", index),
+            Slot::Quick => "quick".to_string(),
+            Slot::Auto(index) => format!("auto_{}", index),
+        }
+    }
+}
+
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Slot::Manual(index) => write!(f, "slot {}", index),
+            Slot::Quick => write!(f, "quick save"),
+            Slot::Auto(index) => write!(f, "autosave {}", index),
+        }
+    }
+}
+
+/// Asks `save_load_system` to write the current game to a slot.
+#[derive(Message)]
+pub struct SaveRequest {
+    pub slot: Slot,
+}
+
+/// Asks `save_load_system` to restore the game from a slot.
+#[derive(Message)]
+pub struct LoadRequest {
+    pub slot: Slot,
+}
+
+pub fn slot_path(slot: Slot) -> PathBuf {
+    PathBuf::from(SAVE_DIR).join(format!("{}.ron", slot.file_stem()))
+}
+
+pub fn write_slot(slot: Slot, data: &SaveData, defs: &CharacterDefs) -> Result<(), SaveError> {
+    let text = ron::ser::to_string_pretty(data, ron::ser::PrettyConfig::default())
+        .map_err(SaveError::Serialize)?;
+
+    fs::create_dir_all(SAVE_DIR)?;
+    fs::write(slot_path(slot), text)?;
+    write_meta(slot, data, defs)
+}
+
+pub fn read_slot(slot: Slot, migrations: &MigrationRegistry) -> Result<SaveData, SaveError> {
+    let text = fs::read_to_string(slot_path(slot))?;
+    parse_save(&text, migrations)
+}
+
+/// Reads a file kept in `SAVE_DIR` next to the slots, such as the persistent
+/// variables. `None` until it is first written.
+pub fn read_save_file<T: DeserializeOwned>(name: &str) -> Result<Option<T>, SaveError> {
+    let text = match fs::read_to_string(PathBuf::from(SAVE_DIR).join(name)) {
+        Ok(text) => text,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    ron::from_str(&text).map(Some).map_err(SaveError::Deserialize)
+}
+
+/// Writes a file into `SAVE_DIR`, creating it if needed.
+pub fn write_save_file<T: Serialize>(name: &str, value: &T) -> Result<(), SaveError> {
+    let text = ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())
+        .map_err(SaveError::Serialize)?;
+
+    fs::create_dir_all(SAVE_DIR)?;
+    fs::write(PathBuf::from(SAVE_DIR).join(name), text)?;
+    Ok(())
+}
+
+/// Migrates a save to the current format version and parses it.
+pub fn parse_save(text: &str, migrations: &MigrationRegistry) -> Result<SaveData, SaveError> {
+    migrations.migrate(text)
+}
+
+/// Captures the runner, variables, scene and play time.
+pub fn capture(runner: &ScriptRunner, ctx: &ScriptContext) -> SaveData {
+    let mut characters: Vec<CharacterSave> = ctx
+        .characters
+        .active
+        .iter()
+        .map(|(name, active)| CharacterSave {
+            name: name.clone(),
+            expression: active.expression.clone(),
+            params: active.params.clone(),
+        })
+        .collect();
+    // Keep slot files stable between saves of the same scene
+    characters.sort_by(|a, b| a.name.cmp(&b.name));
+
+    SaveData {
+        version: SAVE_VERSION,
+        runner: RunnerSave {
+            position: runner.resume_position(),
+            waiting: runner.waiting,
+            call_stack: runner.call_stack.iter().map(|&ip| runner.position_of(ip)).collect(),
+        },
+        vars: ctx.vars.vars.clone(),
+        dialogue: DialogueSave {
+            speaker: ctx.dialogue.speaker.clone(),
+            character: ctx.dialogue.character.clone(),
+            current_line: ctx.dialogue.current_line.clone(),
+        },
+        background: ctx.backgrounds.path.clone(),
+        characters,
+        music: ctx.music.track.clone(),
+        play_time: ctx.play_time.seconds,
+        history: ctx.history.entries.iter().cloned().collect(),
+    }
+}
+
+/// Rebuilds the runner, variables, scene and play time from a save.
+pub fn restore(
+    data: SaveData,
+    runner: &mut ScriptRunner,
+    ctx: &mut ScriptContext,
+) -> Result<(), SaveError> {
+    let resolve = |position: ScriptPosition| {
+        runner.resolve_position(&position).ok_or(SaveError::InvalidPosition(position))
+    };
+
+    let call_stack = data
+        .runner
+        .call_stack
+        .into_iter()
+        .map(|position| resolve(position).map(|(ip, _)| ip))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if !runner.resume_at(&data.runner.position, data.runner.waiting) {
+        return Err(SaveError::InvalidPosition(data.runner.position));
+    }
+    runner.call_stack = call_stack;
+
+    ctx.play_time.seconds = data.play_time;
+    ctx.vars.vars = data.vars;
+    ctx.dialogue.speaker = data.dialogue.speaker;
+    ctx.dialogue.character = data.dialogue.character;
+    ctx.dialogue.current_line = data.dialogue.current_line;
+    ctx.history.entries = data.history.into();
+    stop_voice(&mut ctx.commands, &mut ctx.voice);
+
+    match data.background {
+        Some(path) => set_background_image(
+            &mut ctx.commands,
+            &ctx.asset_server,
+            &mut ctx.backgrounds,
+            path,
+        ),
+        None => clear_background(&mut ctx.commands, &mut ctx.backgrounds),
+    }
+
+    let shown: Vec<String> = ctx.characters.active.keys().cloned().collect();
+    for name in shown {
+        hide_character(&mut ctx.commands, &mut ctx.characters, &name);
+    }
+
+    for character in data.characters {
+        show_character(
+            &mut ctx.commands,
+            &ctx.asset_server,
+            &mut ctx.characters,
+            &ctx.character_defs,
+            character.name,
+            character.expression,
+            character.params,
+        );
+    }
+
+    match data.music {
+        // Don't restart a track that is already playing
+        Some(track) if ctx.music.track.as_ref() == Some(&track) => {}
+        Some(track) => play_music(&mut ctx.commands, &ctx.asset_server, &mut ctx.music, track),
+        None => stop_music(&mut ctx.commands, &mut ctx.music),
+    }
+
+    // A save made while a choice was open shows it again
+    ctx.choice_req.options = None;
+    if runner.waiting_on_choice()
+        && let Some(Instruction::Choice(options)) = runner.instructions.get(runner.ip - 1)
+    {
+        ctx.choice_req.options = Some(options.clone());
+    }
+
+    Ok(())
+}
+
+pub fn save_load_system(
+    mut save_requests: MessageReader<SaveRequest>,
+    mut load_requests: MessageReader<LoadRequest>,
+    choice_roots: Query<Entity, With<ChoiceRoot>>,
+    migrations: Res<MigrationRegistry>,
+    mut runner: ResMut<ScriptRunner>,
+    mut ctx: ScriptContext,
+) {
+    if let Some(data) = ctx.autosaves.pending.take() {
+        let slot = ctx.autosaves.next_slot();
+        match write_slot(slot, &data, &ctx.character_defs) {
+            Ok(()) => info!("saved to {}", slot),
+            Err(err) => error!("failed to save {}: {}", slot, err),
+        }
+    }
+
+    for request in save_requests.read() {
+        match write_slot(request.slot, &capture(&runner, &ctx), &ctx.character_defs) {
+            Ok(()) => info!("saved to {}", request.slot),
+            Err(err) => error!("failed to save {}: {}", request.slot, err),
+        }
+    }
+
+    for request in load_requests.read() {
+        let result = read_slot(request.slot, &migrations)
+            .and_then(|data| restore(data, &mut runner, &mut ctx));
+
+        match result {
+            Ok(()) => {
+                // Any choice on screen belonged to the game being replaced
+                for root in &choice_roots {
+                    ctx.commands.entity(root).despawn();
+                }
+                ctx.rollback.clear();
+                info!("loaded {}", request.slot);
+            }
+            Err(err) => error!("failed to load {}: {}", request.slot, err),
+        }
+    }
+}
+
+/// F5 quick saves and F9 quick loads. Shift+1..9 saves to that slot and
+/// Alt+1..9 loads it, until there is a save screen. Ctrl is left to skip mode.
+pub fn slot_hotkeys(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut saves: MessageWriter<SaveRequest>,
+    mut loads: MessageWriter<LoadRequest>,
+) {
+    const DIGITS: [KeyCode; 9] = [
+        KeyCode::Digit1,
+        KeyCode::Digit2,
+        KeyCode::Digit3,
+        KeyCode::Digit4,
+        KeyCode::Digit5,
+        KeyCode::Digit6,
+        KeyCode::Digit7,
+        KeyCode::Digit8,
+        KeyCode::Digit9,
+    ];
+
+    if keyboard.just_pressed(KeyCode::F5) {
+        saves.write(SaveRequest { slot: Slot::Quick });
+    }
+    if keyboard.just_pressed(KeyCode::F9) {
+        loads.write(LoadRequest { slot: Slot::Quick });
+    }
+
+    let shift = keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let alt = keyboard.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]);
+
+    for (i, key) in DIGITS.iter().enumerate() {
+        if !keyboard.just_pressed(*key) {
+            continue;
+        }
+
+        let slot = Slot::Manual(i as u32 + 1);
+        if shift {
+            saves.write(SaveRequest { slot });
+        } else if alt {
+            loads.write(LoadRequest { slot });
+        }
+    }
+}
@@ -3,23 +3,28 @@ use bevy::prelude::*;
 #[derive(Resource, Default)]
 pub struct BackgroundManager {
     pub current: Option<Entity>,
+    /// Image path of the current background, if it is an image.
+    pub path: Option<String>,
 }
 
 #[derive(Component)]
 pub struct BackgroundTag;
 
+/// Asset path of a background image named in a script.
+pub fn background_path(path: &str) -> String {
+    format!("backgrounds/{}", path)
+}
+
 pub fn set_background_image(
     commands: &mut Commands,
     asset_server: &Res<AssetServer>,
     manager: &mut ResMut<BackgroundManager>,
     path: String,
 ) {
-    // Remove existing background
-    if let Some(entity) = manager.current.take() {
-        commands.entity(entity).despawn();
-    }
+    clear_background(commands, manager);
 
-    let texture: Handle<Image> = asset_server.load(format!("backgrounds/{}", path));
+    let texture: Handle<Image> = asset_server.load(background_path(&path));
+    manager.path = Some(path);
 
     let entity = commands.spawn((
         Sprite::from_image(texture),
@@ -35,10 +40,7 @@ pub fn set_background_color(
     manager: &mut ResMut<BackgroundManager>,
     color: Color,
 ) {
-    // Remove existing background
-    if let Some(entity) = manager.current.take() {
-        commands.entity(entity).despawn();
-    }
+    clear_background(commands, manager);
 
     // Large quad to simulate a background color
     let entity = commands.spawn((
@@ -53,3 +55,13 @@ pub fn set_background_color(
 
     manager.current = Some(entity);
 }
+
+pub fn clear_background(
+    commands: &mut Commands,
+    manager: &mut ResMut<BackgroundManager>,
+) {
+    if let Some(entity) = manager.current.take() {
+        commands.entity(entity).despawn();
+    }
+    manager.path = None;
+}
@@ -1,9 +1,83 @@
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 #[derive(Resource, Default)]
 pub struct CharacterManager {
-    pub active: HashMap<String, Entity>,
+    pub active: HashMap<String, ActiveCharacter>,
+}
+
+/// A character on screen, with what it took to show it.
+pub struct ActiveCharacter {
+    pub entity: Entity,
+    pub expression: String,
+    pub params: TransformParams,
+}
+
+/// A character set up with `define character`. Anything left unset falls
+/// back to what an undefined character gets.
+#[derive(Debug, Clone, Default)]
+pub struct CharacterDef {
+    /// Name shown as the speaker, instead of the character's ID.
+    pub name: Option<String>,
+    pub name_color: Option<Color>,
+    pub text_color: Option<Color>,
+    /// Folder under `characters/` holding the sprites, instead of the ID.
+    pub sprites: Option<String>,
+    /// Expression used by `show` when none is given.
+    pub expression: Option<String>,
+    /// Position preset used by `show` when no position is given.
+    pub position: Option<String>,
+    /// Sound played when a line by the character starts, relative to
+    /// `audio/sfx/`, for lines without a voice clip.
+    pub blip: Option<String>,
+}
+
+/// Where a character stands when no position is given.
+pub const DEFAULT_POSITION: Vec2 = Vec2::new(0.0, -100.0);
+
+/// Position presets every script can use; `define position` adds to and
+/// overrides these.
+pub const BUILTIN_POSITIONS: &[(&str, Vec2)] = &[
+    ("left", Vec2::new(-400.0, -100.0)),
+    ("center", DEFAULT_POSITION),
+    ("right", Vec2::new(400.0, -100.0)),
+];
+
+/// Characters and position presets defined by the running script, by ID.
+#[derive(Resource, Clone)]
+pub struct CharacterDefs {
+    pub defs: HashMap<String, CharacterDef>,
+    /// Spots `show ... at` can place a character, by name.
+    pub positions: HashMap<String, Vec2>,
+}
+
+impl Default for CharacterDefs {
+    fn default() -> Self {
+        Self {
+            defs: HashMap::new(),
+            positions: builtin_positions(),
+        }
+    }
+}
+
+impl CharacterDefs {
+    pub fn get(&self, id: &str) -> Option<&CharacterDef> {
+        self.defs.get(id)
+    }
+
+    /// Swaps in the definitions from a newly loaded script.
+    pub fn set(&mut self, defs: HashMap<String, CharacterDef>, positions: &HashMap<String, Vec2>) {
+        self.defs = defs;
+        self.positions = builtin_positions();
+        self.positions.extend(positions.iter().map(|(name, &position)| (name.clone(), position)));
+    }
+
+    /// Asset path of a character's sprite, looked up in its sprite folder.
+    pub fn sprite_path(&self, id: &str, expression: &str) -> String {
+        let folder = self.get(id).and_then(|def| def.sprites.as_deref()).unwrap_or(id);
+        sprite_path(folder, expression)
+    }
 }
 
 #[derive(Component)]
@@ -11,7 +85,7 @@ pub struct CharacterSprite {
     pub name: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TransformParams {
     pub x: Option<f32>,
     pub y: Option<f32>,
@@ -34,41 +108,20 @@ impl Default for TransformParams {
     }
 }
 
-fn preset_position(name: &str) -> (f32, f32) {
-    match name {
-        "left" => (-400.0, -100.0),
-        "center" => (0.0, -100.0),
-        "right" => (400.0, -100.0),
-        _ => (0.0, -100.0),
-    }
+pub fn builtin_positions() -> HashMap<String, Vec2> {
+    BUILTIN_POSITIONS.iter().map(|&(name, position)| (name.to_string(), position)).collect()
 }
 
-pub fn show_character(
-    commands: &mut Commands,
-    asset_server: &Res<AssetServer>,
-    manager: &mut ResMut<CharacterManager>,
-    name: String,
-    expression: String,
-    params: TransformParams,
-) {
-    // Remove existing sprite
-    if let Some(entity) = manager.active.remove(&name) {
-        commands.entity(entity).despawn();
-    }
-
-    let path = format!("characters/{}/{}.png", name, expression);
-    let texture: Handle<Image> = asset_server.load(path);
-
-    // Base position
-    let (mut x, mut y) = if let Some(ref preset) = params.preset {
-        preset_position(preset)
-    } else {
-        (0.0, -100.0)
-    };
+/// Asset path of a character's sprite for one expression.
+pub fn sprite_path(name: &str, expression: &str) -> String {
+    format!("characters/{}/{}.png", name, expression)
+}
 
-    // Overrides
-    if let Some(v) = params.x { x = v; }
-    if let Some(v) = params.y { y = v; }
+/// Where a character with these parameters is drawn. `show_character`
+/// works the script's presets out into `x` and `y` first.
+pub fn character_transform(params: &TransformParams) -> Transform {
+    let x = params.x.unwrap_or(DEFAULT_POSITION.x);
+    let y = params.y.unwrap_or(DEFAULT_POSITION.y);
 
     // Layer (z)
     let z = params.layer.unwrap_or(10.0);
@@ -83,13 +136,49 @@ pub fn show_character(
         transform.rotation = Quat::from_rotation_z(rot.to_radians());
     }
 
+    transform
+}
+
+pub fn show_character(
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    manager: &mut ResMut<CharacterManager>,
+    defs: &CharacterDefs,
+    name: String,
+    expression: String,
+    mut params: TransformParams,
+) {
+    // Remove existing sprite
+    if let Some(active) = manager.active.remove(&name) {
+        commands.entity(active.entity).despawn();
+    }
+
+    // A defined character stands at its own spot unless told otherwise
+    if params.preset.is_none() && params.x.is_none() && params.y.is_none() {
+        params.preset = defs.get(&name).and_then(|def| def.position.clone());
+    }
+
+    // Explicit x and y win over the preset's
+    if let Some(preset) = &params.preset {
+        match defs.positions.get(preset) {
+            Some(position) => {
+                params.x.get_or_insert(position.x);
+                params.y.get_or_insert(position.y);
+            }
+            None => warn!("show {}: unknown position `{}`", name, preset),
+        }
+    }
+
+    let texture: Handle<Image> = asset_server.load(defs.sprite_path(&name, &expression));
+    let transform = character_transform(&params);
+
     let entity = commands.spawn((
         Sprite::from_image(texture),
         transform,
         CharacterSprite { name: name.clone() },
     )).id();
 
-    manager.active.insert(name, entity);
+    manager.active.insert(name, ActiveCharacter { entity, expression, params });
 }
 
 pub fn hide_character(
@@ -97,7 +186,7 @@ pub fn hide_character(
     manager: &mut ResMut<CharacterManager>,
     name: &str,
 ) {
-    if let Some(entity) = manager.active.remove(name) {
-        commands.entity(entity).despawn();
+    if let Some(active) = manager.active.remove(name) {
+        commands.entity(active.entity).despawn();
     }
 }
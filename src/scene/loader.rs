@@ -1,12 +1,11 @@
 use bevy::prelude::*;
-use crate::script::runner::ScriptRunner;
-use crate::script::loader::load_script;
+use crate::script::asset::ActiveScript;
 
 
 pub fn load_test_scene(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    mut runner: ResMut<ScriptRunner>,
+    mut active: ResMut<ActiveScript>,
 ) {
     commands.spawn(Camera2d);
 
@@ -17,8 +16,6 @@ pub fn load_test_scene(
         Transform::from_xyz(0.0, 0.0, 0.0),
     ));
 
-    // Load script from file
-    runner.instructions = load_script("scripts/test.vn");
-    runner.rebuild_labels();
-    runner.ip = 0;
+    // The runner starts once the script asset has loaded
+    active.handle = asset_server.load("scripts/test.vn");
 }
@@ -0,0 +1,167 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::prelude::*;
+
+use crate::scene::characters::CharacterDefs;
+use crate::script::error::ScriptLoadError;
+use crate::script::loader::{Script, ScriptBuilder};
+use crate::script::runner::ScriptRunner;
+use crate::ui::choices::{ChoiceRequest, ChoiceRoot};
+
+/// A `.vn` script, together with every file it includes.
+#[derive(Asset, TypePath, Debug)]
+pub struct VnScript {
+    pub script: Script,
+}
+
+/// The script the runner is currently executing.
+#[derive(Resource, Default)]
+pub struct ActiveScript {
+    pub handle: Handle<VnScript>,
+}
+
+#[derive(Default, TypePath)]
+pub struct VnScriptLoader;
+
+impl AssetLoader for VnScriptLoader {
+    type Asset = VnScript;
+    type Settings = ();
+    type Error = ScriptLoadError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<VnScript, ScriptLoadError> {
+        let root = load_context.path().path().to_string_lossy().replace('\\', "/");
+        let mut builder = ScriptBuilder::new(&root);
+
+        while let Some(file) = builder.next_file() {
+            // Included files are read through the load context so that editing
+            // any of them hot-reloads the root script
+            let bytes = if file == root {
+                let mut bytes = Vec::new();
+                reader.read_to_end(&mut bytes).await.map(|_| bytes).map_err(|e| e.to_string())
+            } else {
+                load_context.read_asset_bytes(file.clone()).await.map_err(|e| e.to_string())
+            };
+
+            match bytes.and_then(|b| String::from_utf8(b).map_err(|e| e.to_string())) {
+                Ok(source) => builder.add_file(&file, &source),
+                Err(err) => builder.read_failed(&file, format!("failed to read script: {}", err)),
+            }
+        }
+
+        builder
+            .build()
+            .map(|script| VnScript { script })
+            .map_err(ScriptLoadError)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["vn"]
+    }
+}
+
+/// Starts the active script once it has loaded, and swaps in the new version
+/// whenever the file changes on disk.
+#[allow(clippy::too_many_arguments)]
+pub fn apply_script_changes(
+    mut commands: Commands,
+    mut events: MessageReader<AssetEvent<VnScript>>,
+    scripts: Res<Assets<VnScript>>,
+    active: Res<ActiveScript>,
+    choice_roots: Query<Entity, With<ChoiceRoot>>,
+    mut choice_req: ResMut<ChoiceRequest>,
+    mut runner: ResMut<ScriptRunner>,
+    mut character_defs: ResMut<CharacterDefs>,
+) {
+    for event in events.read() {
+        let (AssetEvent::Added { id } | AssetEvent::Modified { id }) = *event else {
+            continue;
+        };
+
+        if id != active.handle.id() {
+            continue;
+        }
+
+        let Some(asset) = scripts.get(id) else {
+            continue;
+        };
+
+        character_defs.set(asset.script.characters.clone(), &asset.script.positions);
+
+        if matches!(event, AssetEvent::Added { .. }) {
+            runner.load(asset.script.clone());
+        } else {
+            info!("script reloaded");
+            runner.reload(asset.script.clone());
+
+            // The runner replays from its label, so an open choice is shown
+            // again from the new script when it is reached
+            for root in &choice_roots {
+                commands.entity(root).despawn();
+            }
+            choice_req.options = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: &str = "\
+label start
+choice:
+    \"Stay\" -> start
+    \"Go\" -> end
+label end
+";
+
+    fn script(src: &str) -> Script {
+        let mut builder = ScriptBuilder::new("test.vn");
+        while let Some(file) = builder.next_file() {
+            builder.add_file(&file, src);
+        }
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn reload_takes_down_an_open_choice() {
+        let mut runner = ScriptRunner::default();
+        runner.load(script(SCRIPT));
+        runner.ip = 2;
+        runner.waiting = true;
+        assert!(runner.waiting_on_choice());
+
+        let mut scripts = Assets::<VnScript>::default();
+        let handle = scripts.add(VnScript { script: script(SCRIPT) });
+        let id = handle.id();
+
+        let mut app = App::new();
+        app.add_message::<AssetEvent<VnScript>>()
+            .insert_resource(scripts)
+            .insert_resource(ActiveScript { handle })
+            .insert_resource(runner)
+            .insert_resource(ChoiceRequest { options: Some(vec![]) })
+            .init_resource::<CharacterDefs>()
+            .add_systems(Update, apply_script_changes);
+        app.world_mut().spawn(ChoiceRoot);
+
+        let edited = script(&SCRIPT.replace("Stay", "Wait"));
+        let mut scripts = app.world_mut().resource_mut::<Assets<VnScript>>();
+        scripts.insert(id, VnScript { script: edited }).unwrap();
+        app.world_mut().write_message(AssetEvent::Modified { id });
+        app.update();
+
+        let mut roots = app.world_mut().query_filtered::<Entity, With<ChoiceRoot>>();
+        assert_eq!(roots.iter(app.world()).count(), 0);
+        assert!(app.world().resource::<ChoiceRequest>().options.is_none());
+
+        let runner = app.world().resource::<ScriptRunner>();
+        assert_eq!(runner.ip, 0);
+        assert!(!runner.waiting);
+    }
+}
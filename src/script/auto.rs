@@ -0,0 +1,105 @@
+use bevy::prelude::*;
+
+use crate::audio::VoiceManager;
+use crate::script::markup;
+use crate::script::runner::{ScriptPosition, ScriptRunner};
+use crate::script::skip::SeenLines;
+use crate::ui::dialogue::{DialogueState, Typewriter};
+use crate::ui::history::HistoryScreen;
+
+/// Default for `AutoForward::chars_per_second`.
+pub const DEFAULT_AUTO_CHARS_PER_SECOND: f32 = 25.0;
+
+/// Default for `AutoForward::min_delay`.
+pub const DEFAULT_AUTO_MIN_DELAY: f32 = 1.5;
+
+/// Advances dialogue by itself once the player has had time to read it.
+#[derive(Resource)]
+pub struct AutoForward {
+    /// Toggled with A.
+    pub active: bool,
+    /// Reading speed the delay is worked out from.
+    pub chars_per_second: f32,
+    /// Shortest time, in seconds, any line stays up.
+    pub min_delay: f32,
+    /// Line the timer is running for, and how long it has been up.
+    line: Option<ScriptPosition>,
+    elapsed: f32,
+}
+
+impl Default for AutoForward {
+    fn default() -> Self {
+        Self {
+            active: false,
+            chars_per_second: DEFAULT_AUTO_CHARS_PER_SECOND,
+            min_delay: DEFAULT_AUTO_MIN_DELAY,
+            line: None,
+            elapsed: 0.0,
+        }
+    }
+}
+
+impl AutoForward {
+    /// Seconds a line of text stays up before auto mode moves on.
+    pub fn delay_for(&self, text: &str) -> f32 {
+        let reading = text.chars().count() as f32 / self.chars_per_second.max(f32::EPSILON);
+        reading.max(self.min_delay)
+    }
+
+    fn reset(&mut self) {
+        self.line = None;
+        self.elapsed = 0.0;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn auto_forward_system(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+    history: Res<HistoryScreen>,
+    dialogue: Res<DialogueState>,
+    typewriter: Res<Typewriter>,
+    voice: Res<VoiceManager>,
+    players: Query<(), With<AudioPlayer>>,
+    mut auto: ResMut<AutoForward>,
+    mut seen: ResMut<SeenLines>,
+    mut runner: ResMut<ScriptRunner>,
+) {
+    if keyboard.just_pressed(KeyCode::KeyA) {
+        auto.active = !auto.active;
+    }
+
+    // Choices and the history screen pause auto mode without turning it off
+    if !auto.active || history.open || runner.waiting_on_choice() {
+        auto.reset();
+        return;
+    }
+
+    let Some(line) = runner.current_line() else {
+        auto.reset();
+        return;
+    };
+
+    if auto.line.as_ref() != Some(&line) {
+        auto.line = Some(line.clone());
+        auto.elapsed = 0.0;
+    }
+
+    // Reading time counts from when the whole line is on screen
+    if typewriter.is_revealing() {
+        return;
+    }
+    auto.elapsed += time.delta_secs();
+
+    let text = markup::strip(dialogue.current_line.as_deref().unwrap_or_default());
+    // A voice clip despawns itself once it has finished
+    let voice_playing = voice.current.is_some_and(|entity| players.contains(entity));
+
+    if auto.elapsed < auto.delay_for(&text) || voice_playing {
+        return;
+    }
+
+    seen.mark(line);
+    runner.waiting = false;
+    auto.reset();
+}
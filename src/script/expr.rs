@@ -1,196 +1,686 @@
+use std::fmt;
+
 use crate::vars::store::{VarStore, Value};
 
-#[derive(Debug, Clone)]
-enum Token {
-    Number(f64),
+/// A script expression, compiled once when the script is loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Value),
     Variable(String),
-    Operator(String),
-    LParen,
-    RParen,
+    Unary {
+        op: UnaryOp,
+        operand: Box<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
 }
 
-fn precedence(op: &str) -> i32 {
-    match op {
-        "or" => 1,
-        "and" => 2,
-        "==" | ">" | "<" | ">=" | "<=" => 3,
-        "+" | "-" => 4,
-        "*" | "/" | "%" => 5,
-        "not" => 6,
-        _ => 0,
-    }
-}
-
-fn is_right_associative(op: &str) -> bool {
-    op == "not"
-}
-
-fn apply_op(a: f64, b: f64, op: &str) -> f64 {
-    match op {
-        "+" => a + b,
-        "-" => a - b,
-        "*" => a * b,
-        "/" => a / b,
-        "%" => a % b,
-        "==" => if (a - b).abs() < f64::EPSILON { 1.0 } else { 0.0 },
-        ">" => if a > b { 1.0 } else { 0.0 },
-        "<" => if a < b { 1.0 } else { 0.0 },
-        ">=" => if a >= b { 1.0 } else { 0.0 },
-        "<=" => if a <= b { 1.0 } else { 0.0 },
-        "and" => if a != 0.0 && b != 0.0 { 1.0 } else { 0.0 },
-        "or" => if a != 0.0 || b != 0.0 { 1.0 } else { 0.0 },
-        _ => 0.0,
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Not,
+    Neg,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Gt,
+    Lt,
+    Ge,
+    Le,
+    And,
+    Or,
 }
 
-fn apply_unary(a: f64, op: &str) -> f64 {
-    match op {
-        "not" => if a == 0.0 { 1.0 } else { 0.0 },
-        _ => a,
+#[derive(Debug, Clone)]
+pub struct ExprError {
+    pub message: String,
+    /// Byte offset into the expression source, for errors found while parsing.
+    pub offset: Option<usize>,
+}
+
+impl ExprError {
+    fn at(offset: usize, message: impl Into<String>) -> Self {
+        Self { message: message.into(), offset: Some(offset) }
+    }
+
+    fn runtime(message: impl Into<String>) -> Self {
+        Self { message: message.into(), offset: None }
     }
 }
 
-fn get_var(vars: &VarStore, name: &str) -> f64 {
-    match vars.get(name) {
-        Some(Value::Int(v)) => *v as f64,
-        Some(Value::Float(v)) => *v as f64,
-        Some(Value::Bool(v)) => if *v { 1.0 } else { 0.0 },
-        _ => 0.0,
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
     }
 }
 
-fn tokenize(expr: &str) -> Vec<Token> {
+impl std::error::Error for ExprError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(Value),
+    Ident(String),
+    Operator(&'static str),
+    LParen,
+    RParen,
+}
+
+fn tokenize(src: &str) -> Result<Vec<(Token, usize)>, ExprError> {
     let mut tokens = Vec::new();
-    let mut buffer = String::new();
+    let mut chars = src.char_indices().peekable();
 
-    let push_buffer = |buf: &mut String, tokens: &mut Vec<Token>| {
-        if buf.is_empty() { return; }
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
 
-        if let Ok(num) = buf.parse::<f64>() {
-            tokens.push(Token::Number(num));
-        } else {
-            tokens.push(Token::Variable(buf.clone()));
+        if c.is_ascii_digit() || c == '.' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_ascii_digit() || c == '.') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+
+            let text = &src[start..end];
+            let value = if text.contains('.') {
+                text.parse::<f32>().map(Value::Float).ok()
+            } else {
+                text.parse::<i64>().map(Value::Int).ok()
+            };
+            let value = value.ok_or_else(|| ExprError::at(start, format!("invalid number `{}`", text)))?;
+            tokens.push((Token::Literal(value), start));
+            continue;
         }
-        buf.clear();
-    };
 
-    let mut chars = expr.chars().peekable();
+        if c == '"' {
+            chars.next();
+            let mut text = String::new();
+            let mut closed = false;
 
-    while let Some(c) = chars.next() {
-        match c {
-            ' ' => push_buffer(&mut buffer, &mut tokens),
+            while let Some((_, c)) = chars.next() {
+                match c {
+                    '"' => {
+                        closed = true;
+                        break;
+                    }
+                    '\\' => match chars.next() {
+                        Some((_, 'n')) => text.push('\n'),
+                        Some((_, c)) => text.push(c),
+                        None => break,
+                    },
+                    _ => text.push(c),
+                }
+            }
 
-            '(' => {
-                push_buffer(&mut buffer, &mut tokens);
-                tokens.push(Token::LParen);
+            if !closed {
+                return Err(ExprError::at(start, "unterminated string literal"));
             }
+            tokens.push((Token::Literal(Value::Text(text)), start));
+            continue;
+        }
 
-            ')' => {
-                push_buffer(&mut buffer, &mut tokens);
-                tokens.push(Token::RParen);
+        if c.is_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_' || c == '.') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
             }
 
-            '+' | '-' | '*' | '/' | '%' => {
-                push_buffer(&mut buffer, &mut tokens);
-                tokens.push(Token::Operator(c.to_string()));
+            let token = match &src[start..end] {
+                "and" => Token::Operator("and"),
+                "or" => Token::Operator("or"),
+                "not" => Token::Operator("not"),
+                "true" => Token::Literal(Value::Bool(true)),
+                "false" => Token::Literal(Value::Bool(false)),
+                name => Token::Ident(name.to_string()),
+            };
+            tokens.push((token, start));
+            continue;
+        }
+
+        chars.next();
+        let next = chars.peek().map(|&(_, c)| c);
+
+        let token = match (c, next) {
+            ('(', _) => Token::LParen,
+            (')', _) => Token::RParen,
+            ('+', _) => Token::Operator("+"),
+            ('-', _) => Token::Operator("-"),
+            ('*', _) => Token::Operator("*"),
+            ('/', _) => Token::Operator("/"),
+            ('%', _) => Token::Operator("%"),
+            ('=', Some('=')) => { chars.next(); Token::Operator("==") }
+            ('!', Some('=')) => { chars.next(); Token::Operator("!=") }
+            ('>', Some('=')) => { chars.next(); Token::Operator(">=") }
+            ('<', Some('=')) => { chars.next(); Token::Operator("<=") }
+            ('>', _) => Token::Operator(">"),
+            ('<', _) => Token::Operator("<"),
+            _ => return Err(ExprError::at(start, format!("unexpected character `{}`", c))),
+        };
+        tokens.push((token, start));
+    }
+
+    Ok(tokens)
+}
+
+fn binary_op(op: &str) -> Option<(BinaryOp, u8)> {
+    let entry = match op {
+        "or" => (BinaryOp::Or, 1),
+        "and" => (BinaryOp::And, 2),
+        "==" => (BinaryOp::Eq, 3),
+        "!=" => (BinaryOp::Ne, 3),
+        ">" => (BinaryOp::Gt, 3),
+        "<" => (BinaryOp::Lt, 3),
+        ">=" => (BinaryOp::Ge, 3),
+        "<=" => (BinaryOp::Le, 3),
+        "+" => (BinaryOp::Add, 4),
+        "-" => (BinaryOp::Sub, 4),
+        "*" => (BinaryOp::Mul, 5),
+        "/" => (BinaryOp::Div, 5),
+        "%" => (BinaryOp::Rem, 5),
+        _ => return None,
+    };
+    Some(entry)
+}
+
+const UNARY_PRECEDENCE: u8 = 6;
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    len: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens.get(self.pos).map(|&(_, o)| o).unwrap_or(self.len)
+    }
+
+    fn expression(&mut self, min_prec: u8) -> Result<Expr, ExprError> {
+        let mut lhs = self.operand()?;
+
+        while let Some(Token::Operator(op)) = self.peek() {
+            let Some((op, prec)) = binary_op(op) else {
+                break;
+            };
+            if prec < min_prec {
+                break;
             }
 
-            '=' | '>' | '<' => {
-                push_buffer(&mut buffer, &mut tokens);
+            self.pos += 1;
+            let rhs = self.expression(prec + 1)?;
+            lhs = Expr::Binary { op, lhs: Box::new(lhs), rhs: Box::new(rhs) };
+        }
 
-                if let Some('=') = chars.peek() {
-                    let next = chars.next().unwrap();
-                    tokens.push(Token::Operator(format!("{}{}", c, next)));
-                } else {
-                    tokens.push(Token::Operator(c.to_string()));
+        Ok(lhs)
+    }
+
+    fn operand(&mut self) -> Result<Expr, ExprError> {
+        let offset = self.offset();
+        let Some((token, _)) = self.tokens.get(self.pos).cloned() else {
+            return Err(ExprError::at(offset, "expected a value"));
+        };
+        self.pos += 1;
+
+        match token {
+            Token::Literal(value) => Ok(Expr::Literal(value)),
+            Token::Ident(name) => Ok(Expr::Variable(name)),
+            Token::Operator("not") => {
+                let operand = self.expression(UNARY_PRECEDENCE)?;
+                Ok(Expr::Unary { op: UnaryOp::Not, operand: Box::new(operand) })
+            }
+            Token::Operator("-") => {
+                let operand = self.expression(UNARY_PRECEDENCE)?;
+                Ok(Expr::Unary { op: UnaryOp::Neg, operand: Box::new(operand) })
+            }
+            Token::LParen => {
+                let inner = self.expression(0)?;
+                if self.peek() != Some(&Token::RParen) {
+                    return Err(ExprError::at(self.offset(), "expected `)`"));
                 }
+                self.pos += 1;
+                Ok(inner)
             }
+            Token::RParen => Err(ExprError::at(offset, "unexpected `)`")),
+            Token::Operator(op) => Err(ExprError::at(offset, format!("unexpected operator `{}`", op))),
+        }
+    }
+}
+
+/// Compiles an expression such as `coins + 1 >= 3 and not seen_intro`.
+pub fn parse(src: &str) -> Result<Expr, ExprError> {
+    let mut parser = Parser {
+        tokens: tokenize(src)?,
+        pos: 0,
+        len: src.len(),
+    };
 
-            _ => buffer.push(c),
+    let expr = parser.expression(0)?;
+
+    if parser.pos < parser.tokens.len() {
+        return Err(ExprError::at(parser.offset(), "unexpected token after expression"));
+    }
+
+    Ok(expr)
+}
+
+fn type_mismatch(op: BinaryOp, a: &Value, b: &Value) -> ExprError {
+    ExprError::runtime(format!(
+        "type mismatch: cannot apply `{}` to {} and {}",
+        op.symbol(),
+        a.type_name(),
+        b.type_name(),
+    ))
+}
+
+impl BinaryOp {
+    fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Lt => "<",
+            BinaryOp::Ge => ">=",
+            BinaryOp::Le => "<=",
+            BinaryOp::And => "and",
+            BinaryOp::Or => "or",
         }
     }
+}
 
-    push_buffer(&mut buffer, &mut tokens);
+fn arithmetic(op: BinaryOp, a: Value, b: Value) -> Result<Value, ExprError> {
+    let overflow = || ExprError::runtime(format!("integer overflow in `{}`", op.symbol()));
 
-    // Convert keywords
-    for token in &mut tokens {
-        if let Token::Variable(s) = token {
-            if s == "and" || s == "or" || s == "not" {
-                *token = Token::Operator(s.clone());
+    match (&a, &b) {
+        (Value::Int(x), Value::Int(y)) => {
+            let (x, y) = (*x, *y);
+            if matches!(op, BinaryOp::Div | BinaryOp::Rem) && y == 0 {
+                return Err(ExprError::runtime("division by zero"));
             }
+
+            let result = match op {
+                BinaryOp::Add => x.checked_add(y),
+                BinaryOp::Sub => x.checked_sub(y),
+                BinaryOp::Mul => x.checked_mul(y),
+                BinaryOp::Div => x.checked_div(y),
+                BinaryOp::Rem => x.checked_rem(y),
+                _ => unreachable!(),
+            };
+            result.map(Value::Int).ok_or_else(overflow)
         }
+
+        (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+            let (x, y) = (as_float(&a), as_float(&b));
+            if matches!(op, BinaryOp::Div | BinaryOp::Rem) && y == 0.0 {
+                return Err(ExprError::runtime("division by zero"));
+            }
+
+            let result = match op {
+                BinaryOp::Add => x + y,
+                BinaryOp::Sub => x - y,
+                BinaryOp::Mul => x * y,
+                BinaryOp::Div => x / y,
+                BinaryOp::Rem => x % y,
+                _ => unreachable!(),
+            };
+            Ok(Value::Float(result as f32))
+        }
+
+        (Value::Text(x), Value::Text(y)) if op == BinaryOp::Add => {
+            Ok(Value::Text(format!("{}{}", x, y)))
+        }
+
+        _ => Err(type_mismatch(op, &a, &b)),
     }
+}
 
-    tokens
+/// A variable that has never been set equals only another unset one, so
+/// `route == "alice"` works before `route` has been set. Set values of
+/// different kinds can't be compared.
+fn equals(op: BinaryOp, a: &Operand, b: &Operand) -> Result<bool, ExprError> {
+    let (Operand::Value(a), Operand::Value(b)) = (a, b) else {
+        return Ok(matches!((a, b), (Operand::Unset(_), Operand::Unset(_))));
+    };
+
+    match (a, b) {
+        (Value::Int(x), Value::Int(y)) => Ok(x == y),
+        (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+            Ok((as_float(a) - as_float(b)).abs() < f64::EPSILON)
+        }
+        (Value::Bool(x), Value::Bool(y)) => Ok(x == y),
+        (Value::Text(x), Value::Text(y)) => Ok(x == y),
+        _ => Err(type_mismatch(op, a, b)),
+    }
 }
 
-pub fn eval(expr: &str, vars: &VarStore) -> f64 {
-    let tokens = tokenize(expr);
+fn compare(op: BinaryOp, a: &Value, b: &Value) -> Result<Value, ExprError> {
+    let ordering = match (a, b) {
+        (Value::Int(x), Value::Int(y)) => x.partial_cmp(y),
+        (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+            as_float(a).partial_cmp(&as_float(b))
+        }
+        (Value::Text(x), Value::Text(y)) => x.partial_cmp(y),
+        _ => return Err(type_mismatch(op, a, b)),
+    };
 
-    let mut values: Vec<f64> = Vec::new();
-    let mut ops: Vec<String> = Vec::new();
+    let Some(ordering) = ordering else {
+        return Ok(Value::Bool(false));
+    };
 
-    let mut i = 0;
-    while i < tokens.len() {
-        match &tokens[i] {
-            Token::Number(n) => values.push(*n),
+    let result = match op {
+        BinaryOp::Gt => ordering.is_gt(),
+        BinaryOp::Lt => ordering.is_lt(),
+        BinaryOp::Ge => ordering.is_ge(),
+        BinaryOp::Le => ordering.is_le(),
+        _ => unreachable!(),
+    };
+    Ok(Value::Bool(result))
+}
 
-            Token::Variable(name) => {
-                values.push(get_var(vars, name));
-            }
+fn as_float(value: &Value) -> f64 {
+    match value {
+        Value::Int(v) => *v as f64,
+        Value::Float(v) => *v as f64,
+        _ => 0.0,
+    }
+}
 
-            Token::LParen => ops.push("(".into()),
+/// What an expression evaluates to on the way to its result: a value, or a
+/// variable that has never been set.
+enum Operand {
+    Value(Value),
+    Unset(String),
+}
 
-            Token::RParen => {
-                while ops.last().map(|s| s.as_str()) != Some("(") {
-                    process_op(&mut values, &mut ops);
+impl Operand {
+    fn value(self) -> Result<Value, ExprError> {
+        match self {
+            Operand::Value(value) => Ok(value),
+            Operand::Unset(name) => Err(ExprError::runtime(format!("`{}` is not set", name))),
+        }
+    }
+
+    /// Unset variables are false, so flags don't need declaring up front.
+    fn is_truthy(&self) -> bool {
+        match self {
+            Operand::Value(value) => value.is_truthy(),
+            Operand::Unset(_) => false,
+        }
+    }
+}
+
+impl Expr {
+    /// Evaluates the expression. Using a variable that has never been set is
+    /// an error, except in `==`, `!=`, `not`, `and` and `or`.
+    pub fn eval(&self, vars: &VarStore) -> Result<Value, ExprError> {
+        self.operand(vars)?.value()
+    }
+
+    /// Evaluates the expression as a condition, where a variable that has
+    /// never been set is false.
+    pub fn holds(&self, vars: &VarStore) -> Result<bool, ExprError> {
+        Ok(self.operand(vars)?.is_truthy())
+    }
+
+    fn operand(&self, vars: &VarStore) -> Result<Operand, ExprError> {
+        let value = match self {
+            Expr::Literal(value) => value.clone(),
+
+            Expr::Variable(name) => match vars.get(name) {
+                Some(value) => value.clone(),
+                None => return Ok(Operand::Unset(name.clone())),
+            },
+
+            Expr::Unary { op: UnaryOp::Not, operand } => Value::Bool(!operand.holds(vars)?),
+
+            Expr::Unary { op: UnaryOp::Neg, operand } => match operand.eval(vars)? {
+                Value::Int(v) => v
+                    .checked_neg()
+                    .map(Value::Int)
+                    .ok_or_else(|| ExprError::runtime("integer overflow in `-`"))?,
+                Value::Float(v) => Value::Float(-v),
+                other => {
+                    return Err(ExprError::runtime(format!(
+                        "type mismatch: cannot negate {}",
+                        other.type_name(),
+                    )))
                 }
-                ops.pop();
+            },
+
+            Expr::Binary { op: BinaryOp::And, lhs, rhs } => {
+                Value::Bool(lhs.holds(vars)? && rhs.holds(vars)?)
             }
 
-            Token::Operator(op) => {
-                while let Some(top) = ops.last() {
-                    if top == "(" {
-                        break;
-                    }
+            Expr::Binary { op: BinaryOp::Or, lhs, rhs } => {
+                Value::Bool(lhs.holds(vars)? || rhs.holds(vars)?)
+            }
+
+            Expr::Binary { op: op @ (BinaryOp::Eq | BinaryOp::Ne), lhs, rhs } => {
+                let equal = equals(*op, &lhs.operand(vars)?, &rhs.operand(vars)?)?;
+                Value::Bool(equal == (*op == BinaryOp::Eq))
+            }
 
-                    let cond = if is_right_associative(op) {
-                        precedence(top) > precedence(op)
-                    } else {
-                        precedence(top) >= precedence(op)
-                    };
+            Expr::Binary { op, lhs, rhs } => {
+                let a = lhs.eval(vars)?;
+                let b = rhs.eval(vars)?;
 
-                    if cond {
-                        process_op(&mut values, &mut ops);
-                    } else {
-                        break;
-                    }
+                match op {
+                    BinaryOp::Gt | BinaryOp::Lt | BinaryOp::Ge | BinaryOp::Le => compare(*op, &a, &b)?,
+                    _ => arithmetic(*op, a, b)?,
                 }
-
-                ops.push(op.clone());
             }
-        }
+        };
 
-        i += 1;
+        Ok(Operand::Value(value))
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    while !ops.is_empty() {
-        process_op(&mut values, &mut ops);
+    fn int(v: i64) -> Expr {
+        Expr::Literal(Value::Int(v))
     }
 
-    values.pop().unwrap_or(0.0)
-}
+    fn var(name: &str) -> Expr {
+        Expr::Variable(name.to_string())
+    }
 
-fn process_op(values: &mut Vec<f64>, ops: &mut Vec<String>) {
-    let op = ops.pop().unwrap();
+    fn unary(op: UnaryOp, operand: Expr) -> Expr {
+        Expr::Unary { op, operand: Box::new(operand) }
+    }
+
+    fn binary(op: BinaryOp, lhs: Expr, rhs: Expr) -> Expr {
+        Expr::Binary { op, lhs: Box::new(lhs), rhs: Box::new(rhs) }
+    }
+
+    fn eval(src: &str, vars: &VarStore) -> Result<Value, String> {
+        parse(src).unwrap().eval(vars).map_err(|err| err.message)
+    }
+
+    fn text(v: &str) -> Value {
+        Value::Text(v.to_string())
+    }
+
+    /// Message and offset of the error `src` fails to compile with.
+    fn parse_error(src: &str) -> (String, Option<usize>) {
+        let err = parse(src).unwrap_err();
+        (err.message, err.offset)
+    }
 
-    if op == "not" {
-        let a = values.pop().unwrap();
-        values.push(apply_unary(a, &op));
-        return;
+    #[test]
+    fn binary_operators_are_left_associative() {
+        assert_eq!(
+            parse("2 - 1 - 1").unwrap(),
+            binary(BinaryOp::Sub, binary(BinaryOp::Sub, int(2), int(1)), int(1)),
+        );
+        assert_eq!(
+            parse("8 / 4 / 2").unwrap(),
+            binary(BinaryOp::Div, binary(BinaryOp::Div, int(8), int(4)), int(2)),
+        );
     }
 
-    let b = values.pop().unwrap();
-    let a = values.pop().unwrap();
-    values.push(apply_op(a, b, &op));
+    #[test]
+    fn operators_bind_by_precedence() {
+        assert_eq!(
+            parse("not a and b").unwrap(),
+            binary(BinaryOp::And, unary(UnaryOp::Not, var("a")), var("b")),
+        );
+        assert_eq!(
+            parse("1 + 2 * 3").unwrap(),
+            binary(BinaryOp::Add, int(1), binary(BinaryOp::Mul, int(2), int(3))),
+        );
+        assert_eq!(
+            parse("-x * 2").unwrap(),
+            binary(BinaryOp::Mul, unary(UnaryOp::Neg, var("x")), int(2)),
+        );
+        assert_eq!(
+            parse("a or b and coins + 1 >= 3").unwrap(),
+            binary(
+                BinaryOp::Or,
+                var("a"),
+                binary(
+                    BinaryOp::And,
+                    var("b"),
+                    binary(BinaryOp::Ge, binary(BinaryOp::Add, var("coins"), int(1)), int(3)),
+                ),
+            ),
+        );
+        assert_eq!(
+            parse("(1 + 2) * 3").unwrap(),
+            binary(BinaryOp::Mul, binary(BinaryOp::Add, int(1), int(2)), int(3)),
+        );
+    }
+
+    #[test]
+    fn reports_errors_where_they_start() {
+        let error = |message: &str, offset: usize| (message.to_string(), Some(offset));
+
+        assert_eq!(parse_error("route == \"alice"), error("unterminated string literal", 9));
+        assert_eq!(parse_error("x + 1.2.3"), error("invalid number `1.2.3`", 4));
+        assert_eq!(parse_error("coins +"), error("expected a value", 7));
+        assert_eq!(parse_error("1 + * 2"), error("unexpected operator `*`", 4));
+        assert_eq!(parse_error("(1 + 2"), error("expected `)`", 6));
+        assert_eq!(parse_error("1 + 2)"), error("unexpected token after expression", 5));
+        assert_eq!(parse_error(")"), error("unexpected `)`", 0));
+        assert_eq!(parse_error("a = b"), error("unexpected character `=`", 2));
+    }
+
+    #[test]
+    fn arithmetic_keeps_ints_and_widens_to_floats() {
+        let vars = VarStore::default();
+
+        assert_eq!(eval("2 + 3", &vars), Ok(Value::Int(5)));
+        assert_eq!(eval("7 / 2", &vars), Ok(Value::Int(3)));
+        assert_eq!(eval("7 % 4", &vars), Ok(Value::Int(3)));
+        assert_eq!(eval("1 + 0.5", &vars), Ok(Value::Float(1.5)));
+        assert_eq!(eval("0.5 * 4", &vars), Ok(Value::Float(2.0)));
+    }
+
+    #[test]
+    fn text_concatenates_and_compares() {
+        let mut vars = VarStore::default();
+        vars.set("route", text("alice"));
+
+        assert_eq!(eval("\"ali\" + \"ce\"", &vars), Ok(text("alice")));
+        assert_eq!(eval("route == \"alice\"", &vars), Ok(Value::Bool(true)));
+        assert_eq!(eval("route != \"alice\"", &vars), Ok(Value::Bool(false)));
+        assert_eq!(eval("route != \"bob\"", &vars), Ok(Value::Bool(true)));
+        assert_eq!(eval("route < \"bob\"", &vars), Ok(Value::Bool(true)));
+        assert_eq!(eval("1 == 1.0", &vars), Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn unset_variables_are_false_and_unequal_to_any_value() {
+        let mut vars = VarStore::default();
+        vars.set("coins", Value::Int(0));
+        let holds = |src: &str| parse(src).unwrap().holds(&vars).map_err(|err| err.message);
+
+        assert_eq!(holds("seen"), Ok(false));
+        assert_eq!(holds("not seen and not coins"), Ok(true));
+        assert_eq!(eval("route == \"alice\"", &vars), Ok(Value::Bool(false)));
+        assert_eq!(eval("seen != 0", &vars), Ok(Value::Bool(true)));
+        assert_eq!(eval("seen == other", &vars), Ok(Value::Bool(true)));
+        assert_eq!(eval("coins == seen", &vars), Ok(Value::Bool(false)));
+
+        assert_eq!(eval("seen", &vars), Err("`seen` is not set".to_string()));
+        assert_eq!(eval("seen + 1", &vars), Err("`seen` is not set".to_string()));
+        assert_eq!(eval("seen < 1", &vars), Err("`seen` is not set".to_string()));
+        assert_eq!(eval("-seen", &vars), Err("`seen` is not set".to_string()));
+    }
+
+    #[test]
+    fn unary_minus_negates_numbers() {
+        let mut vars = VarStore::default();
+        vars.set("min", Value::Int(i64::MIN));
+
+        assert_eq!(eval("-3", &vars), Ok(Value::Int(-3)));
+        assert_eq!(eval("-1.5", &vars), Ok(Value::Float(-1.5)));
+        assert_eq!(eval("--3", &vars), Ok(Value::Int(3)));
+        assert_eq!(eval("-min", &vars), Err("integer overflow in `-`".to_string()));
+        assert_eq!(eval("min - 1", &vars), Err("integer overflow in `-`".to_string()));
+        assert_eq!(eval("-\"a\"", &vars), Err("type mismatch: cannot negate text".to_string()));
+    }
+
+    #[test]
+    fn division_by_zero_is_an_error() {
+        let vars = VarStore::default();
+
+        assert_eq!(eval("1 / 0", &vars), Err("division by zero".to_string()));
+        assert_eq!(eval("1 % 0", &vars), Err("division by zero".to_string()));
+        assert_eq!(eval("1.5 / 0", &vars), Err("division by zero".to_string()));
+    }
+
+    #[test]
+    fn mismatched_types_name_the_operator_and_types() {
+        let vars = VarStore::default();
+
+        assert_eq!(
+            eval("1 + \"a\"", &vars),
+            Err("type mismatch: cannot apply `+` to int and text".to_string()),
+        );
+        assert_eq!(
+            eval("\"a\" - \"b\"", &vars),
+            Err("type mismatch: cannot apply `-` to text and text".to_string()),
+        );
+        assert_eq!(
+            eval("true > 0.5", &vars),
+            Err("type mismatch: cannot apply `>` to bool and float".to_string()),
+        );
+        assert_eq!(
+            eval("\"1\" == 1", &vars),
+            Err("type mismatch: cannot apply `==` to text and int".to_string()),
+        );
+        assert_eq!(
+            eval("true != 1", &vars),
+            Err("type mismatch: cannot apply `!=` to bool and int".to_string()),
+        );
+    }
 }
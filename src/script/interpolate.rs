@@ -0,0 +1,203 @@
+use bevy::log::warn;
+
+use crate::script::markup::MarkupError;
+use crate::vars::store::{Value, VarStore};
+
+/// A variable placeholder, `[name]` or `{$name}`, with an optional format
+/// after a colon: `[price:.2]` shows a number with two decimals.
+#[derive(Debug, Clone, PartialEq)]
+struct Placeholder<'a> {
+    name: &'a str,
+    decimals: Option<usize>,
+}
+
+impl Placeholder<'_> {
+    fn format(&self, value: &Value) -> String {
+        match (value, self.decimals) {
+            (Value::Int(v), Some(decimals)) => format!("{:.*}", decimals, *v as f64),
+            (Value::Float(v), Some(decimals)) => format!("{:.*}", decimals, v),
+            (value, _) => value.to_string(),
+        }
+    }
+}
+
+/// Checks every placeholder in `src`, so mistakes are reported when the
+/// script loads rather than shown to the player.
+pub fn check(src: &str) -> Result<(), MarkupError> {
+    let mut result = Ok(());
+    scan(src, |offset, spec| {
+        if result.is_ok()
+            && let Err(message) = parse_placeholder(spec)
+        {
+            result = Err(MarkupError { message, offset });
+        }
+        None
+    })?;
+    result
+}
+
+/// Replaces placeholders with the current values of their variables.
+/// Placeholders for unset variables are left as written, so bracketed text
+/// such as `[sighs]` isn't lost, and a warning names them.
+pub fn resolve(src: &str, vars: &VarStore) -> String {
+    substitute(src, vars, false)
+}
+
+/// Like `resolve`, for text that is parsed as markup afterwards: braces in
+/// the values are escaped so they show up as written.
+pub fn resolve_markup(src: &str, vars: &VarStore) -> String {
+    substitute(src, vars, true)
+}
+
+fn substitute(src: &str, vars: &VarStore, escape: bool) -> String {
+    let resolved = scan(src, |_, spec| {
+        // Lines are checked when the script loads; anything that still doesn't
+        // parse is left as written
+        let placeholder = parse_placeholder(spec).ok()?;
+        let Some(value) = vars.get(placeholder.name) else {
+            warn!("`{}` is not set; the placeholder is shown as written", placeholder.name);
+            return None;
+        };
+        let text = placeholder.format(value);
+
+        Some(if escape { text.replace('{', "{{").replace('}', "}}") } else { text })
+    });
+
+    resolved.unwrap_or_else(|_| src.to_string())
+}
+
+/// Walks `src`, calling `replace` with the offset and contents of each
+/// placeholder. Placeholders it returns `None` for are kept as written.
+fn scan(
+    src: &str,
+    mut replace: impl FnMut(usize, &str) -> Option<String>,
+) -> Result<String, MarkupError> {
+    let mut out = String::with_capacity(src.len());
+    let mut rest = src;
+
+    while let Some(start) = rest.find(['[', '{']) {
+        out.push_str(&rest[..start]);
+        let offset = src.len() - rest.len() + start;
+        let tail = &rest[start..];
+
+        // `[[` is a literal bracket; `{{` is markup's to unescape
+        if let Some(after) = tail.strip_prefix("[[") {
+            out.push('[');
+            rest = after;
+            continue;
+        }
+        if let Some(after) = tail.strip_prefix("{{") {
+            out.push_str("{{");
+            rest = after;
+            continue;
+        }
+
+        let (open, close) = if tail.starts_with('[') {
+            ("[", ']')
+        } else if tail.starts_with("{$") {
+            ("{$", '}')
+        } else {
+            // A markup tag
+            out.push('{');
+            rest = &tail[1..];
+            continue;
+        };
+
+        let Some(len) = tail.find(close) else {
+            return Err(MarkupError { message: format!("unclosed `{}`", open), offset });
+        };
+
+        let placeholder = &tail[..=len];
+        match replace(offset, &tail[open.len()..len]) {
+            Some(text) => out.push_str(&text),
+            None => out.push_str(placeholder),
+        }
+        rest = &tail[len + 1..];
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn parse_placeholder(spec: &str) -> Result<Placeholder<'_>, String> {
+    let (name, format) = match spec.split_once(':') {
+        Some((name, format)) => (name.trim(), Some(format.trim())),
+        None => (spec.trim(), None),
+    };
+
+    let valid_name = name.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '.');
+    if !valid_name {
+        return Err(format!("invalid variable name `{}`", name));
+    }
+
+    let decimals = match format {
+        None => None,
+        Some(format) => match format.strip_prefix('.').and_then(|n| n.parse().ok()) {
+            Some(decimals) => Some(decimals),
+            None => return Err(format!("invalid format `{}`, expected `.N` for N decimals", format)),
+        },
+    };
+
+    Ok(Placeholder { name, decimals })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> VarStore {
+        let mut vars = VarStore::default();
+        vars.set("coins", Value::Int(3));
+        vars.set("price", Value::Float(2.5));
+        vars.set("player_name", Value::Text("{Sam}".to_string()));
+        vars
+    }
+
+    #[test]
+    fn resolves_both_placeholder_forms() {
+        let vars = vars();
+
+        assert_eq!(resolve("You have [coins] coins", &vars), "You have 3 coins");
+        assert_eq!(resolve("You have {$coins} coins", &vars), "You have 3 coins");
+        assert_eq!(resolve("[price:.2] and [coins:.1]", &vars), "2.50 and 3.0");
+        assert_eq!(resolve("[[coins] [coins]", &vars), "[coins] 3");
+    }
+
+    #[test]
+    fn leaves_placeholders_for_unset_variables_as_written() {
+        let vars = vars();
+
+        assert_eq!(resolve("[sighs] Fine. [coins]", &vars), "[sighs] Fine. 3");
+        assert_eq!(resolve_markup("{$unset:.2} {b}x{/b}", &vars), "{$unset:.2} {b}x{/b}");
+    }
+
+    #[test]
+    fn leaves_markup_alone_and_escapes_values_in_it() {
+        let vars = vars();
+
+        assert_eq!(resolve("{b}[coins]{/b} {{", &vars), "{b}3{/b} {{");
+        assert_eq!(resolve_markup("Hi {$player_name}", &vars), "Hi {{Sam}}");
+        assert_eq!(resolve("Hi {$player_name}", &vars), "Hi {Sam}");
+    }
+
+    #[test]
+    fn reports_bad_placeholders() {
+        assert_eq!(
+            check("x [coins"),
+            Err(MarkupError { message: "unclosed `[`".to_string(), offset: 2 }),
+        );
+        assert_eq!(
+            check("[1st]"),
+            Err(MarkupError { message: "invalid variable name `1st`".to_string(), offset: 0 }),
+        );
+        assert_eq!(
+            check("ok {$price:2}"),
+            Err(MarkupError {
+                message: "invalid format `2`, expected `.N` for N decimals".to_string(),
+                offset: 3,
+            }),
+        );
+        assert_eq!(check("{b}[coins]{/b} {$price:.2}"), Ok(()));
+    }
+}
@@ -1,94 +1,1274 @@
-use std::fs;
-use bevy::prelude::Color;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
 
+use bevy::color::Srgba;
+use bevy::math::Vec2;
+
+use crate::script::error::ScriptError;
+use crate::script::expr::{self, Expr, UnaryOp};
+use crate::script::{interpolate, markup};
 use crate::script::runner::Instruction;
-use crate::scene::characters::TransformParams;
+use crate::scene::characters::{BUILTIN_POSITIONS, CharacterDef, DEFAULT_POSITION, TransformParams};
+use crate::vars::store::PERSISTENT_PREFIX;
+
+/// A parsed script, ready to be handed to the `ScriptRunner`.
+#[derive(Debug, Clone, Default)]
+pub struct Script {
+    pub instructions: Vec<Instruction>,
+    pub labels: HashMap<String, usize>,
+    /// Characters from `define character` blocks, by ID.
+    pub characters: HashMap<String, CharacterDef>,
+    /// Positions from `define position` blocks, by name.
+    pub positions: HashMap<String, Vec2>,
+}
+
+/// Assembles a `Script` from a root file and the files it includes.
+///
+/// The caller reads each file returned by `next_file` and hands its source to
+/// `add_file`, so the builder works with any way of reading files.
+pub struct ScriptBuilder {
+    files: Vec<ParsedFile>,
+    queued: HashSet<String>,
+    pending: VecDeque<String>,
+    // path -> (including file, line, column) of the `include` that pulled it in
+    included_from: HashMap<String, (String, usize, usize)>,
+    errors: Vec<ScriptError>,
+}
+
+impl ScriptBuilder {
+    pub fn new(root: &str) -> Self {
+        Self {
+            files: Vec::new(),
+            queued: HashSet::from([root.to_string()]),
+            pending: VecDeque::from([root.to_string()]),
+            included_from: HashMap::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// The next file that still needs to be read.
+    pub fn next_file(&mut self) -> Option<String> {
+        self.pending.pop_front()
+    }
+
+    pub fn add_file(&mut self, path: &str, source: &str) {
+        let mut parser = Parser {
+            file: path,
+            lines: source.lines().collect(),
+            pos: 0,
+            parsed: ParsedFile {
+                path: path.to_string(),
+                ..Default::default()
+            },
+            loops: Vec::new(),
+            errors: Vec::new(),
+        };
+
+        parser.parse();
+        self.errors.append(&mut parser.errors);
+
+        for (include, line, column) in &parser.parsed.includes {
+            let resolved = resolve_include(path, include);
+            if self.queued.insert(resolved.clone()) {
+                self.included_from.insert(resolved.clone(), (path.to_string(), *line, *column));
+                self.pending.push_back(resolved);
+            }
+        }
+
+        self.files.push(parser.parsed);
+    }
+
+    /// Records that `path` could not be read, blaming the `include` that asked
+    /// for it. The root script has no `include`, so its error points at its start.
+    pub fn read_failed(&mut self, path: &str, message: impl Into<String>) {
+        let error = match self.included_from.get(path) {
+            Some((file, line, column)) => ScriptError::new(file, *line, *column, message),
+            None => ScriptError::new(path, 1, 1, message),
+        };
+        self.errors.push(error);
+    }
+
+    /// Merges every file into one instruction list and resolves labels across files.
+    pub fn build(mut self) -> Result<Script, Vec<ScriptError>> {
+        let mut script = Script::default();
+        // label -> (file, line) of its definition
+        let mut defined: HashMap<String, (String, usize)> = HashMap::new();
+        let mut namespaces: HashMap<String, String> = HashMap::new();
+
+        for file in &self.files {
+            let base = script.instructions.len();
+            let namespace = script_name(&file.path);
 
-pub fn load_script(path: &str) -> Vec<Instruction> {
-    let content = fs::read_to_string(format!("assets/{}", path))
-        .expect("Failed to read script");
+            if let Some(other) = namespaces.insert(namespace.clone(), file.path.clone()) {
+                self.errors.push(ScriptError::new(
+                    &file.path,
+                    1,
+                    1,
+                    format!("script name `{}` is also used by {}", namespace, other),
+                ));
+            }
+
+            for label in &file.labels {
+                if let Some((other_file, other_line)) = defined.get(&label.name) {
+                    self.errors.push(ScriptError::new(
+                        &file.path,
+                        label.line,
+                        label.column,
+                        format!(
+                            "duplicate label `{}` (first defined at {}:{})",
+                            label.name, other_file, other_line,
+                        ),
+                    ));
+                    continue;
+                }
 
-    let mut instructions = Vec::new();
+                defined.insert(label.name.clone(), (file.path.clone(), label.line));
+                script.labels.insert(label.name.clone(), base + label.index);
 
-    for raw_line in content.lines() {
-        let line = raw_line.trim();
+                if !is_generated_label(&label.name) {
+                    script.labels.insert(format!("{}.{}", namespace, label.name), base + label.index);
+                }
+            }
 
-        if line.is_empty() || line.starts_with('#') {
-            continue;
+            script.instructions.extend(file.instructions.iter().cloned());
+            // Falling off the end of a file stops the script instead of
+            // running into whichever file happens to come next
+            script.instructions.push(Instruction::End);
         }
 
+        // character -> (file, line) of its definition
+        let mut characters: HashMap<String, (String, usize)> = HashMap::new();
+        for file in &self.files {
+            for (id, def, line, column) in &file.characters {
+                if let Some((other_file, other_line)) = characters.get(id) {
+                    self.errors.push(ScriptError::new(
+                        &file.path,
+                        *line,
+                        *column,
+                        format!(
+                            "duplicate character `{}` (first defined at {}:{})",
+                            id, other_file, other_line,
+                        ),
+                    ));
+                    continue;
+                }
+
+                characters.insert(id.clone(), (file.path.clone(), *line));
+                script.characters.insert(id.clone(), def.clone());
+            }
+        }
+
+        // position -> (file, line) of its definition
+        let mut positions: HashMap<String, (String, usize)> = HashMap::new();
+        for file in &self.files {
+            for (name, position, line, column) in &file.positions {
+                if let Some((other_file, other_line)) = positions.get(name) {
+                    self.errors.push(ScriptError::new(
+                        &file.path,
+                        *line,
+                        *column,
+                        format!(
+                            "duplicate position `{}` (first defined at {}:{})",
+                            name, other_file, other_line,
+                        ),
+                    ));
+                    continue;
+                }
+
+                positions.insert(name.clone(), (file.path.clone(), *line));
+                script.positions.insert(name.clone(), *position);
+            }
+        }
+
+        for file in &self.files {
+            for (name, line, column) in &file.position_refs {
+                let known = script.positions.contains_key(name)
+                    || BUILTIN_POSITIONS.iter().any(|(builtin, _)| builtin == name);
+
+                if !known {
+                    self.errors.push(ScriptError::new(
+                        &file.path,
+                        *line,
+                        *column,
+                        format!("unknown position `{}`", name),
+                    ));
+                }
+            }
+        }
+
+        for file in &self.files {
+            for (name, line, column) in &file.default_shows {
+                let has_default = script
+                    .characters
+                    .get(name)
+                    .is_some_and(|def| def.expression.is_some());
+
+                if !has_default {
+                    self.errors.push(ScriptError::new(
+                        &file.path,
+                        *line,
+                        *column,
+                        format!("`show {}` needs an expression; `{}` has no default", name, name),
+                    ));
+                }
+            }
+        }
+
+        for file in &self.files {
+            for (target, line, column) in &file.jump_targets {
+                if !script.labels.contains_key(target) {
+                    self.errors.push(ScriptError::new(
+                        &file.path,
+                        *line,
+                        *column,
+                        format!("undefined label `{}`", target),
+                    ));
+                }
+            }
+        }
+
+        if self.errors.is_empty() {
+            Ok(script)
+        } else {
+            self.errors.sort_by(|a, b| (&a.file, a.line, a.column).cmp(&(&b.file, b.line, b.column)));
+            Err(self.errors)
+        }
+    }
+}
+
+/// One script file after parsing, before labels are resolved across files.
+#[derive(Default)]
+struct ParsedFile {
+    path: String,
+    instructions: Vec<Instruction>,
+    labels: Vec<LabelDef>,
+    // (path as written, line, column) of every `include`
+    includes: Vec<(String, usize, usize)>,
+    // (target, line, column) of every label reference, checked once all labels are known
+    jump_targets: Vec<(String, usize, usize)>,
+    // (id, definition, line, column) of every `define character`
+    characters: Vec<(String, CharacterDef, usize, usize)>,
+    // (name, line, column) of every `show` relying on a default expression
+    default_shows: Vec<(String, usize, usize)>,
+    // (name, position, line, column) of every `define position`
+    positions: Vec<(String, Vec2, usize, usize)>,
+    // (name, line, column) of every position preset used
+    position_refs: Vec<(String, usize, usize)>,
+}
+
+struct LabelDef {
+    name: String,
+    index: usize,
+    line: usize,
+    column: usize,
+}
+
+struct Parser<'a> {
+    file: &'a str,
+    lines: Vec<&'a str>,
+    pos: usize,
+    parsed: ParsedFile,
+    // (continue, break) labels of the enclosing `while` loops
+    loops: Vec<(String, String)>,
+    errors: Vec<ScriptError>,
+}
+
+impl<'a> Parser<'a> {
+    fn parse(&mut self) {
+        self.parse_block(None);
+    }
+
+    /// Parses statements until a line indented at or below `parent_indent`.
+    fn parse_block(&mut self, parent_indent: Option<usize>) {
+        while let Some((raw_line, line)) = self.peek_line() {
+            if parent_indent.is_some_and(|parent| indent_of(raw_line) <= parent) {
+                break;
+            }
+
+            self.pos += 1;
+            self.parse_line(raw_line, line);
+        }
+    }
+
+    /// Parses the indented body of the block opened on line `line_no`.
+    fn parse_body(&mut self, indent: usize, line_no: usize) {
+        let start = self.parsed.instructions.len();
+        let errors = self.errors.len();
+
+        self.parse_block(Some(indent));
+
+        if self.parsed.instructions.len() == start && self.errors.len() == errors {
+            self.error(line_no, indent + 1, "expected an indented block");
+        }
+    }
+
+    /// The next line that isn't blank or a comment, without consuming it.
+    fn peek_line(&mut self) -> Option<(&'a str, &'a str)> {
+        while self.pos < self.lines.len() {
+            let raw_line = self.lines[self.pos];
+            let line = raw_line.trim();
+
+            if !line.is_empty() && !line.starts_with('#') {
+                return Some((raw_line, line));
+            }
+            self.pos += 1;
+        }
+        None
+    }
+
+    fn parse_line(&mut self, raw_line: &'a str, line: &'a str) {
+        let line_no = self.pos;
+
         if let Some(rest) = line.strip_prefix("label ") {
-            instructions.push(Instruction::Label(rest.trim().to_string()));
-            continue;
+            let name = rest.trim();
+            if !is_identifier(name) {
+                self.error(line_no, column_of(raw_line, rest), format!("invalid label name `{}`", name));
+                return;
+            }
+
+            self.push_label(name.to_string(), line_no, column_of(raw_line, rest));
+            return;
         }
 
-        if let Some(rest) = line.strip_prefix("say ") {
+        if let Some(rest) = line.strip_prefix("include ") {
+            let path = rest.trim();
+            match path.strip_prefix('"').and_then(|p| p.strip_suffix('"')) {
+                Some(path) if !path.is_empty() => {
+                    self.parsed.includes.push((path.to_string(), line_no, column_of(raw_line, rest)));
+                }
+                _ => self.error(line_no, column_of(raw_line, rest), "expected `include \"<file>\"`"),
+            }
+            return;
+        }
+
+        if let Some(rest) = line.strip_prefix("import ") {
+            let name = rest.trim();
+            if is_identifier(name) {
+                self.parsed.includes.push((format!("{}.vn", name), line_no, column_of(raw_line, rest)));
+            } else {
+                self.error(line_no, column_of(raw_line, rest), format!("invalid script name `{}`", name));
+            }
+            return;
+        }
 
-        if let Some((speaker, text)) = rest.split_once(':') {
-                instructions.push(Instruction::Say {
+        if let Some(rest) = line.strip_prefix("say ") {
+            if let Some((speaker, text)) = split_speaker(rest) {
+                self.check_placeholders(raw_line, speaker.trim());
+                self.check_markup(raw_line, text.trim());
+                self.parsed.instructions.push(Instruction::Say {
                     speaker: Some(speaker.trim().to_string()),
                     text: text.trim().to_string(),
                 });
-                } else {
-                    instructions.push(Instruction::Say {
-                        speaker: None,
-                        text: rest.trim().to_string(),
-                    });
-                }
-
-            continue;
+            } else {
+                self.check_markup(raw_line, rest.trim());
+                self.parsed.instructions.push(Instruction::Say {
+                    speaker: None,
+                    text: rest.trim().to_string(),
+                });
+            }
+            return;
         }
 
         if let Some(rest) = line.strip_prefix("jump ") {
-            instructions.push(Instruction::JumpLabel(rest.trim().to_string()));
-            continue;
+            let target = self.label_ref(raw_line, rest);
+            self.parsed.instructions.push(Instruction::JumpLabel(target));
+            return;
+        }
+
+        if let Some(rest) = line.strip_prefix("call ") {
+            let target = self.label_ref(raw_line, rest);
+            self.parsed.instructions.push(Instruction::Call(target));
+            return;
+        }
+
+        if line == "return" {
+            self.parsed.instructions.push(Instruction::Return);
+            return;
+        }
+
+        if line == "autosave" {
+            self.parsed.instructions.push(Instruction::Autosave);
+            return;
+        }
+
+        if let Some(rest) = line.strip_prefix("set ") {
+            let Some((name, expression)) = rest.split_once('=') else {
+                self.error(line_no, column_of(raw_line, rest), "expected `set <name> = <expression>`");
+                return;
+            };
+
+            let name = name.trim();
+            if !is_identifier(name.strip_prefix(PERSISTENT_PREFIX).unwrap_or(name)) {
+                self.error(line_no, column_of(raw_line, rest), format!("invalid variable name `{}`", name));
+                return;
+            }
+
+            let Some(expression) = self.compile(raw_line, expression) else {
+                return;
+            };
+
+            self.parsed.instructions.push(Instruction::SetVar {
+                name: name.to_string(),
+                expression,
+            });
+            return;
+        }
+
+        if let Some(rest) = line.strip_prefix("if ") {
+            if let Some(condition) = rest.strip_suffix(':') {
+                self.parse_if(raw_line, condition);
+                return;
+            }
+
+            let Some((condition, target)) = rest.rsplit_once(" jump ") else {
+                self.error(line_no, column_of(raw_line, rest), "expected `if <condition> jump <label>`");
+                return;
+            };
+
+            let target = self.label_ref(raw_line, target);
+            let Some(condition) = self.compile(raw_line, condition) else {
+                return;
+            };
+
+            self.parsed.instructions.push(Instruction::IfJump { condition, target });
+            return;
+        }
+
+        if line == "choice:" {
+            self.parse_choice(raw_line);
+            return;
+        }
+
+        if let Some(rest) = line.strip_prefix("define character ") {
+            let Some(id) = rest.strip_suffix(':').map(str::trim) else {
+                self.error(line_no, column_of(raw_line, rest), "expected `define character <id>:`");
+                return;
+            };
+
+            if !is_identifier(id) {
+                self.error(line_no, column_of(raw_line, rest), format!("invalid character id `{}`", id));
+                return;
+            }
+
+            self.parse_character(raw_line, id);
+            return;
+        }
+
+        if let Some(rest) = line.strip_prefix("define position ") {
+            let Some(name) = rest.strip_suffix(':').map(str::trim) else {
+                self.error(line_no, column_of(raw_line, rest), "expected `define position <name>:`");
+                return;
+            };
+
+            if !is_identifier(name) {
+                self.error(line_no, column_of(raw_line, rest), format!("invalid position name `{}`", name));
+                return;
+            }
+
+            self.parse_position(raw_line, name);
+            return;
         }
 
         if let Some(rest) = line.strip_prefix("music play ") {
-            instructions.push(Instruction::MusicPlay(rest.trim().to_string()));
-            continue;
+            self.parsed.instructions.push(Instruction::MusicPlay(rest.trim().to_string()));
+            return;
         }
 
         if line == "music stop" {
-            instructions.push(Instruction::MusicStop);
-            continue;
+            self.parsed.instructions.push(Instruction::MusicStop);
+            return;
+        }
+
+        if let Some(rest) = line.strip_prefix("voice play ") {
+            self.parsed.instructions.push(Instruction::VoicePlay(rest.trim().to_string()));
+            return;
         }
 
         if let Some(rest) = line.strip_prefix("sfx play ") {
-            instructions.push(Instruction::SfxPlay(rest.trim().to_string()));
-            continue;
+            self.parsed.instructions.push(Instruction::SfxPlay(rest.trim().to_string()));
+            return;
         }
 
         if let Some(rest) = line.strip_prefix("bg ") {
             if let Some(path) = rest.strip_prefix("image=") {
-                instructions.push(Instruction::BgImage(path.trim().to_string()));
-                continue;
+                self.parsed.instructions.push(Instruction::BgImage(path.trim().to_string()));
+            } else {
+                self.error(line_no, column_of(raw_line, rest), "expected `bg image=<path>`");
             }
+            return;
         }
 
         if let Some(rest) = line.strip_prefix("show ") {
-            let parts: Vec<&str> = rest.split_whitespace().collect();
-            if parts.len() >= 2 {
-                let name = parts[0].to_string();
-                let expression = parts[1].to_string();
-                let params = TransformParams::default();
-
-                instructions.push(Instruction::ShowCharacter {
-                    name,
-                    expression,
-                    params,
-                });
-            }
-            continue;
+            self.parse_show(raw_line, rest);
+            return;
         }
 
         if let Some(rest) = line.strip_prefix("hide ") {
-            instructions.push(Instruction::HideCharacter {
+            self.parsed.instructions.push(Instruction::HideCharacter {
                 name: rest.trim().to_string(),
             });
-            continue;
+            return;
+        }
+
+        if let Some(condition) = line.strip_prefix("while ").and_then(|c| c.strip_suffix(':')) {
+            self.parse_while(raw_line, condition);
+            return;
+        }
+
+        if line == "break" || line == "continue" {
+            let Some((continue_label, break_label)) = self.loops.last() else {
+                self.error(line_no, column_of(raw_line, line), format!("`{}` outside of a loop", line));
+                return;
+            };
+
+            let target = if line == "break" { break_label } else { continue_label };
+            self.parsed.instructions.push(Instruction::JumpLabel(target.clone()));
+            return;
+        }
+
+        if line.starts_with("elif ") || line == "else:" {
+            self.error(line_no, column_of(raw_line, line), "`elif`/`else` without a matching `if`");
+            return;
+        }
+
+        let command = line.split_whitespace().next().unwrap_or(line);
+        self.error(line_no, column_of(raw_line, line), format!("unknown command `{}`", command));
+    }
+
+    /// Lowers an `if`/`elif`/`else` chain to conditional jumps between
+    /// generated labels.
+    fn parse_if(&mut self, raw_line: &'a str, condition: &'a str) {
+        let line_no = self.pos;
+        let indent = indent_of(raw_line);
+        let end_label = self.generated_label(line_no, "end");
+
+        let mut branch = 0;
+        let (mut branch_line, mut branch_condition) = (raw_line, condition);
+
+        loop {
+            let next_label = self.generated_label(line_no, &format!("branch{}", branch));
+
+            if let Some(condition) = self.compile(branch_line, branch_condition) {
+                self.parsed.instructions.push(Instruction::IfJump {
+                    condition: Expr::Unary { op: UnaryOp::Not, operand: Box::new(condition) },
+                    target: next_label.clone(),
+                });
+            }
+
+            self.parse_body(indent, self.pos);
+            self.parsed.instructions.push(Instruction::JumpLabel(end_label.clone()));
+            self.push_label(next_label, self.pos, indent + 1);
+            branch += 1;
+
+            let Some((next_raw, next)) = self.peek_line() else {
+                break;
+            };
+            if indent_of(next_raw) != indent {
+                break;
+            }
+
+            if let Some(condition) = next.strip_prefix("elif ").and_then(|c| c.strip_suffix(':')) {
+                self.pos += 1;
+                (branch_line, branch_condition) = (next_raw, condition);
+                continue;
+            }
+
+            if next == "else:" {
+                self.pos += 1;
+                self.parse_body(indent, self.pos);
+            }
+            break;
+        }
+
+        self.push_label(end_label, self.pos, indent + 1);
+    }
+
+    /// Lowers a `while` loop to a conditional jump past the body and a jump
+    /// back to the condition.
+    fn parse_while(&mut self, raw_line: &'a str, condition: &'a str) {
+        let line_no = self.pos;
+        let indent = indent_of(raw_line);
+        let loop_label = self.generated_label(line_no, "loop");
+        let end_label = self.generated_label(line_no, "end");
+
+        self.push_label(loop_label.clone(), line_no, indent + 1);
+
+        if let Some(condition) = self.compile(raw_line, condition) {
+            self.parsed.instructions.push(Instruction::IfJump {
+                condition: Expr::Unary { op: UnaryOp::Not, operand: Box::new(condition) },
+                target: end_label.clone(),
+            });
+        }
+
+        self.loops.push((loop_label.clone(), end_label.clone()));
+        self.parse_body(indent, line_no);
+        self.loops.pop();
+
+        self.parsed.instructions.push(Instruction::JumpLabel(loop_label));
+        self.push_label(end_label, self.pos, indent + 1);
+    }
+
+    fn parse_choice(&mut self, raw_line: &str) {
+        let line_no = self.pos;
+        let indent = indent_of(raw_line);
+        let mut options = Vec::new();
+
+        // Options are the following lines indented deeper than `choice:`
+        while self.pos < self.lines.len() {
+            let option_line = self.lines[self.pos];
+            let trimmed = option_line.trim();
+
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                self.pos += 1;
+                continue;
+            }
+
+            if indent_of(option_line) <= indent {
+                break;
+            }
+
+            self.pos += 1;
+
+            let Some((text, label)) = trimmed.rsplit_once("->") else {
+                self.error(self.pos, column_of(option_line, trimmed), "expected `\"Text\" -> label`");
+                continue;
+            };
+
+            let Some(text) = text.trim().strip_prefix('"').and_then(|t| t.strip_suffix('"')) else {
+                self.error(self.pos, column_of(option_line, trimmed), "choice text must be quoted");
+                continue;
+            };
+
+            let label = self.label_ref(option_line, label);
+            options.push((text.to_string(), label));
+        }
+
+        if options.is_empty() {
+            self.error(line_no, indent + 1, "choice block has no options");
+        }
+
+        self.parsed.instructions.push(Instruction::Choice(options));
+    }
+
+    /// Parses the `key value` lines of a `define character` block.
+    fn parse_character(&mut self, raw_line: &str, id: &str) {
+        let line_no = self.pos;
+        let indent = indent_of(raw_line);
+        let column = column_of(raw_line, raw_line.trim());
+        let mut def = CharacterDef::default();
+
+        while let Some((field_line, trimmed)) = self.peek_line() {
+            if indent_of(field_line) <= indent {
+                break;
+            }
+            self.pos += 1;
+
+            let (key, value) = trimmed.split_once(char::is_whitespace).unwrap_or((trimmed, ""));
+            let value = value.trim();
+            let value_column = column_of(field_line, value);
+
+            if value.is_empty() {
+                self.error(self.pos, column_of(field_line, trimmed), format!("`{}` needs a value", key));
+                continue;
+            }
+
+            // Values may be quoted, to allow spaces in names
+            let text = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value)
+                .to_string();
+
+            match key {
+                "name" => {
+                    self.check_placeholders(field_line, value);
+                    def.name = Some(text);
+                }
+                "name_color" | "text_color" => {
+                    let Ok(color) = Srgba::hex(&text) else {
+                        self.error(self.pos, value_column, format!("invalid color `{}`", value));
+                        continue;
+                    };
+
+                    if key == "name_color" {
+                        def.name_color = Some(color.into());
+                    } else {
+                        def.text_color = Some(color.into());
+                    }
+                }
+                "sprites" => def.sprites = Some(text),
+                "expression" => def.expression = Some(text),
+                "position" => {
+                    self.parsed.position_refs.push((text.clone(), self.pos, value_column));
+                    def.position = Some(text);
+                }
+                "blip" => def.blip = Some(text),
+                _ => self.error(
+                    self.pos,
+                    column_of(field_line, trimmed),
+                    format!("unknown character field `{}`", key),
+                ),
+            }
+        }
+
+        self.parsed.characters.push((id.to_string(), def, line_no, column));
+    }
+
+    /// Parses the `x` and `y` lines of a `define position` block. Either may
+    /// be left out to keep the default.
+    fn parse_position(&mut self, raw_line: &str, name: &str) {
+        let line_no = self.pos;
+        let indent = indent_of(raw_line);
+        let column = column_of(raw_line, raw_line.trim());
+        let mut position = DEFAULT_POSITION;
+
+        while let Some((field_line, trimmed)) = self.peek_line() {
+            if indent_of(field_line) <= indent {
+                break;
+            }
+            self.pos += 1;
+
+            let (key, value) = trimmed.split_once(char::is_whitespace).unwrap_or((trimmed, ""));
+            let value = value.trim();
+
+            let field = match key {
+                "x" => &mut position.x,
+                "y" => &mut position.y,
+                _ => {
+                    self.error(
+                        self.pos,
+                        column_of(field_line, trimmed),
+                        format!("unknown position field `{}`, expected `x` or `y`", key),
+                    );
+                    continue;
+                }
+            };
+
+            match value.parse::<f32>() {
+                Ok(number) if number.is_finite() => *field = number,
+                _ => self.error(
+                    self.pos,
+                    column_of(field_line, if value.is_empty() { trimmed } else { value }),
+                    format!("`{}` needs a number, got `{}`", key, value),
+                ),
+            }
+        }
+
+        self.parsed.positions.push((name.to_string(), position, line_no, column));
+    }
+
+    /// Parses `show <name> [expression] [at <position>] [key=value...]`.
+    fn parse_show(&mut self, raw_line: &str, rest: &str) {
+        let line_no = self.pos;
+        let mut tokens = rest.split_whitespace().peekable();
+
+        let Some(name) = tokens.next() else {
+            self.error(line_no, column_of(raw_line, rest), "expected `show <name> [expression]`");
+            return;
+        };
+
+        let expression = tokens
+            .next_if(|token| *token != "at" && !token.contains('='))
+            .map(str::to_string);
+        if expression.is_none() {
+            let column = column_of(raw_line, name);
+            self.parsed.default_shows.push((name.to_string(), line_no, column));
+        }
+
+        let mut params = TransformParams::default();
+
+        while let Some(token) = tokens.next() {
+            let column = column_of(raw_line, token);
+
+            if token == "at" {
+                let Some(preset) = tokens.next() else {
+                    self.error(line_no, column, "expected a position after `at`");
+                    return;
+                };
+
+                let column = column_of(raw_line, preset);
+                self.parsed.position_refs.push((preset.to_string(), line_no, column));
+                params.preset = Some(preset.to_string());
+                continue;
+            }
+
+            let Some((key, value)) = token.split_once('=') else {
+                self.error(line_no, column, format!("unexpected `{}`, expected `at` or `key=value`", token));
+                return;
+            };
+
+            let field = match key {
+                "x" => &mut params.x,
+                "y" => &mut params.y,
+                "scale" => &mut params.scale,
+                "rotate" => &mut params.rotation_deg,
+                "layer" => &mut params.layer,
+                _ => {
+                    self.error(
+                        line_no,
+                        column,
+                        format!("unknown `show` option `{}`, expected x, y, scale, rotate or layer", key),
+                    );
+                    return;
+                }
+            };
+
+            match value.parse::<f32>() {
+                Ok(number) if number.is_finite() => *field = Some(number),
+                _ => {
+                    self.error(line_no, column, format!("`{}` needs a number, got `{}`", key, value));
+                    return;
+                }
+            }
+        }
+
+        self.parsed.instructions.push(Instruction::ShowCharacter {
+            name: name.to_string(),
+            expression,
+            params,
+        });
+    }
+
+    /// Compiles an expression on the current line, reporting errors at their
+    /// position within the line.
+    fn compile(&mut self, raw_line: &str, src: &str) -> Option<Expr> {
+        match expr::parse(src) {
+            Ok(expr) => Some(expr),
+            Err(err) => {
+                let offset = err.offset.unwrap_or(0);
+                self.error(self.pos, column_of(raw_line, &src[offset..]), err.message);
+                None
+            }
+        }
+    }
+
+    /// Reports broken variable placeholders on the current line. They are
+    /// filled in when the line is shown.
+    fn check_placeholders(&mut self, raw_line: &str, text: &str) {
+        if let Err(err) = interpolate::check(text) {
+            self.error(self.pos, column_of(raw_line, &text[err.offset..]), err.message);
+        }
+    }
+
+    /// Reports broken text markup or placeholders on the current line. The
+    /// text is parsed again when it is shown.
+    fn check_markup(&mut self, raw_line: &str, text: &str) {
+        self.check_placeholders(raw_line, text);
+
+        if let Err(err) = markup::parse(text) {
+            self.error(self.pos, column_of(raw_line, &text[err.offset..]), err.message);
+        }
+    }
+
+    /// Validates a label reference on the current line and records it for the
+    /// undefined-label check in `ScriptBuilder::build`.
+    fn label_ref(&mut self, raw_line: &str, target: &str) -> String {
+        let column = column_of(raw_line, target);
+        let name = target.trim();
+
+        if !is_label_ref(name) {
+            self.error(self.pos, column, format!("invalid label name `{}`", name));
+        } else {
+            self.parsed.jump_targets.push((name.to_string(), self.pos, column));
+        }
+
+        name.to_string()
+    }
+
+    fn push_label(&mut self, name: String, line: usize, column: usize) {
+        self.parsed.labels.push(LabelDef {
+            name: name.clone(),
+            index: self.parsed.instructions.len(),
+            line,
+            column,
+        });
+
+        self.parsed.instructions.push(Instruction::Label(name));
+    }
+
+    /// A label for control flow lowered by the parser. The `@` prefix keeps it
+    /// out of the namespace writers can use.
+    fn generated_label(&self, line: usize, suffix: &str) -> String {
+        format!("@{}:{}:{}", self.file, line, suffix)
+    }
+
+    fn error(&mut self, line: usize, column: usize, message: impl Into<String>) {
+        self.errors.push(ScriptError::new(self.file, line, column, message));
+    }
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// 1-based column of `part`, which must be a slice of `line`.
+fn column_of(line: &str, part: &str) -> usize {
+    let offset = part.as_ptr() as usize - line.as_ptr() as usize;
+    let leading = part.len() - part.trim_start().len();
+
+    line[..offset + leading].chars().count() + 1
+}
+
+/// Splits `say speaker: text` at the first colon outside a placeholder or
+/// tag, so `say [name:.0]: hi` keeps its format.
+fn split_speaker(rest: &str) -> Option<(&str, &str)> {
+    let mut depth = 0usize;
+
+    for (i, c) in rest.char_indices() {
+        match c {
+            '[' | '{' => depth += 1,
+            ']' | '}' => depth = depth.saturating_sub(1),
+            ':' if depth == 0 => return Some((&rest[..i], &rest[i + 1..])),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Whether `name` was generated by the parser rather than written in a script.
+/// Generated labels move whenever the script is edited, so they make poor
+/// anchors for resuming.
+pub fn is_generated_label(name: &str) -> bool {
+    name.starts_with('@')
+}
+
+/// A label, optionally qualified with its script name (`chapter2.start`).
+fn is_label_ref(name: &str) -> bool {
+    match name.split_once('.') {
+        Some((script, label)) => is_identifier(script) && is_identifier(label),
+        None => is_identifier(name),
+    }
+}
+
+/// The name other scripts use to qualify labels in `path`: its file stem.
+fn script_name(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string())
+}
+
+/// Resolves an included path relative to the directory of the including file.
+fn resolve_include(from: &str, include: &str) -> String {
+    match Path::new(from).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => {
+            format!("{}/{}", dir.to_string_lossy(), include)
         }
+        _ => include.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vars::store::{Value, VarStore};
+
+    fn load(src: &str) -> Result<Script, Vec<ScriptError>> {
+        let mut builder = ScriptBuilder::new("test.vn");
+        while let Some(file) = builder.next_file() {
+            builder.add_file(&file, src);
+        }
+        builder.build()
+    }
+
+    fn instructions(src: &str) -> Vec<Instruction> {
+        load(src).unwrap().instructions
     }
 
-    instructions
+    fn expr(src: &str) -> Expr {
+        expr::parse(src).unwrap()
+    }
+
+    /// Walks the lowered control flow starting from `vars`, returning the
+    /// text of every `say` reached.
+    fn run(src: &str, vars: &mut VarStore) -> Vec<String> {
+        let script = load(src).unwrap();
+        let mut said = Vec::new();
+        let mut ip = 0;
+
+        for _ in 0..10_000 {
+            match &script.instructions[ip] {
+                Instruction::Say { text, .. } => said.push(text.clone()),
+                Instruction::SetVar { name, expression } => {
+                    let value = expression.eval(vars).unwrap();
+                    vars.set(name, value);
+                }
+                Instruction::JumpLabel(target) => {
+                    ip = script.labels[target];
+                    continue;
+                }
+                Instruction::IfJump { condition, target }
+                    if condition.holds(vars).unwrap() =>
+                {
+                    ip = script.labels[target];
+                    continue;
+                }
+                Instruction::End => return said,
+                _ => {}
+            }
+            ip += 1;
+        }
+
+        panic!("script never reached its end");
+    }
+
+    fn vars(values: &[(&str, i64)]) -> VarStore {
+        let mut vars = VarStore::default();
+        for &(name, value) in values {
+            vars.set(name, Value::Int(value));
+        }
+        vars
+    }
+
+    /// (line, column, message) of every error, in the order reported.
+    fn errors(src: &str) -> Vec<(usize, usize, String)> {
+        load(src)
+            .unwrap_err()
+            .into_iter()
+            .map(|err| (err.line, err.column, err.message))
+            .collect()
+    }
+
+    #[test]
+    fn unreadable_files_are_blamed_on_their_include() {
+        let mut builder = ScriptBuilder::new("main.vn");
+        builder.next_file();
+        builder.add_file("main.vn", "say hi\ninclude \"routes/alice.vn\"");
+        while let Some(file) = builder.next_file() {
+            builder.read_failed(&file, "not found");
+        }
+        builder.read_failed("main.vn", "not found");
+
+        let errors: Vec<_> = builder
+            .build()
+            .unwrap_err()
+            .into_iter()
+            .map(|err| (err.file, err.line, err.column))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![("main.vn".to_string(), 1, 1), ("main.vn".to_string(), 2, 9)],
+        );
+    }
+
+    #[test]
+    fn parses_set_if_jump_and_choice() {
+        let src = "\
+label start
+set coins = coins + 1
+if coins >= 3 jump rich
+choice:
+    \"Stay\" -> start
+    # a comment between options
+    \"Go\" -> rich
+label rich
+";
+
+        assert_eq!(
+            instructions(src),
+            vec![
+                Instruction::Label("start".to_string()),
+                Instruction::SetVar { name: "coins".to_string(), expression: expr("coins + 1") },
+                Instruction::IfJump { condition: expr("coins >= 3"), target: "rich".to_string() },
+                Instruction::Choice(vec![
+                    ("Stay".to_string(), "start".to_string()),
+                    ("Go".to_string(), "rich".to_string()),
+                ]),
+                Instruction::Label("rich".to_string()),
+                Instruction::End,
+            ],
+        );
+    }
+
+    #[test]
+    fn set_accepts_typed_literals() {
+        let values: Vec<_> = instructions("set a = 1\nset b = 1.5\nset c = \"alice\"\nset d = true")
+            .into_iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::SetVar { expression: Expr::Literal(value), .. } => Some(value),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(
+            values,
+            vec![
+                Value::Int(1),
+                Value::Float(1.5),
+                Value::Text("alice".to_string()),
+                Value::Bool(true),
+            ],
+        );
+    }
+
+    #[test]
+    fn set_persistent_variables_apart_from_the_game_ones() {
+        let src = "set persistent.route_a_done = true\nset route_a_done = persistent.route_a_done";
+        let mut vars = VarStore::default();
+        run(src, &mut vars);
+
+        assert_eq!(vars.persistent.get("route_a_done"), Some(&Value::Bool(true)));
+        assert!(vars.persistent_changed);
+        assert_eq!(vars.vars.get("route_a_done"), Some(&Value::Bool(true)));
+        assert_eq!(vars.vars.get("persistent.route_a_done"), None);
+
+        assert_eq!(errors("set persistent. = 1\nset persistent.a.b = 1").len(), 2);
+    }
+
+    #[test]
+    fn reports_every_error_in_one_pass_in_line_order() {
+        let src = "\
+set 1x = 2
+say fine
+    dance wildly
+set x = (1 + 2
+show
+if x jump nowhere
+choice:
+    Go -> start
+";
+
+        assert_eq!(
+            errors(src),
+            vec![
+                (1, 5, "invalid variable name `1x`".to_string()),
+                (3, 5, "unknown command `dance`".to_string()),
+                (4, 15, "expected `)`".to_string()),
+                (5, 1, "unknown command `show`".to_string()),
+                (6, 11, "undefined label `nowhere`".to_string()),
+                (7, 1, "choice block has no options".to_string()),
+                (8, 5, "choice text must be quoted".to_string()),
+            ],
+        );
+    }
+
+    #[test]
+    fn columns_count_characters_not_bytes() {
+        assert_eq!(
+            errors("if é jump nowhere\nset ü = 1 +"),
+            vec![
+                (1, 11, "undefined label `nowhere`".to_string()),
+                (2, 12, "expected a value".to_string()),
+            ],
+        );
+    }
+
+    #[test]
+    fn if_elif_else_takes_one_branch() {
+        let src = "\
+if coins >= 10:
+    say rich
+elif coins > 0:
+    say getting by
+    if coins == 1:
+        say down to the last one
+else:
+    say broke
+say done
+";
+
+        assert_eq!(run(src, &mut vars(&[("coins", 12)])), ["rich", "done"]);
+        assert_eq!(run(src, &mut vars(&[("coins", 3)])), ["getting by", "done"]);
+        assert_eq!(run(src, &mut vars(&[("coins", 1)])), ["getting by", "down to the last one", "done"]);
+        assert_eq!(run(src, &mut vars(&[("coins", 0)])), ["broke", "done"]);
+    }
+
+    #[test]
+    fn if_without_else_falls_through() {
+        let src = "if seen:\n    say again\nsay next";
+
+        assert_eq!(run(src, &mut vars(&[("seen", 1)])), ["again", "next"]);
+        assert_eq!(run(src, &mut vars(&[])), ["next"]);
+    }
+
+    #[test]
+    fn branches_need_a_body() {
+        assert_eq!(
+            errors("if a:\nsay after"),
+            vec![(1, 1, "expected an indented block".to_string())],
+        );
+        assert_eq!(
+            errors("  if a:\n    say yes\n  else:\n  say after"),
+            vec![(3, 3, "expected an indented block".to_string())],
+        );
+        assert_eq!(
+            errors("say hi\nelse:\n    say no"),
+            vec![(2, 1, "`elif`/`else` without a matching `if`".to_string())],
+        );
+    }
+
+    #[test]
+    fn while_runs_nested_branches_with_break_and_continue() {
+        let src = "\
+while i < 6:
+    set i = i + 1
+    if i == 2:
+        continue
+    elif i == 5:
+        break
+    else:
+        say step
+        if i == 3:
+            say three
+say done
+";
+
+        assert_eq!(run(src, &mut vars(&[("i", 0)])), ["step", "step", "three", "step", "done"]);
+        assert_eq!(run(src, &mut vars(&[("i", 6)])), ["done"]);
+    }
+
+    #[test]
+    fn break_and_continue_target_the_innermost_loop() {
+        let src = "\
+while a:
+    while b:
+        break
+    if c:
+        continue
+    break
+";
+
+        let targets: Vec<_> = instructions(src)
+            .into_iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::JumpLabel(target) => Some(target),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(
+            targets,
+            [
+                "@test.vn:2:end",  // inner `break`
+                "@test.vn:2:loop", // back to the inner condition
+                "@test.vn:1:loop", // `continue`
+                "@test.vn:4:end",  // end of the `if`
+                "@test.vn:1:end",  // outer `break`
+                "@test.vn:1:loop", // back to the outer condition
+            ],
+        );
+    }
+
+    #[test]
+    fn loop_errors() {
+        assert_eq!(
+            errors("break\nwhile a:\n    say x\ncontinue"),
+            vec![
+                (1, 1, "`break` outside of a loop".to_string()),
+                (4, 1, "`continue` outside of a loop".to_string()),
+            ],
+        );
+        assert_eq!(
+            errors("while a:\nsay x"),
+            vec![(1, 1, "expected an indented block".to_string())],
+        );
+    }
 }
@@ -0,0 +1,239 @@
+use std::fmt;
+
+use bevy::prelude::*;
+
+/// Styling that applies to a run of dialogue text.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SpanStyle {
+    pub bold: bool,
+    pub italic: bool,
+    pub color: Option<Color>,
+    pub size: Option<f32>,
+    /// Reveal speed in characters per second, overriding the typewriter's.
+    pub speed: Option<f32>,
+}
+
+/// A piece of a dialogue line once its tags are parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Piece {
+    Text { text: String, style: SpanStyle },
+    /// Pause the reveal for this many seconds.
+    Wait(f32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarkupError {
+    pub message: String,
+    /// Byte offset of the offending tag in the line.
+    pub offset: usize,
+}
+
+impl MarkupError {
+    fn at(offset: usize, message: impl Into<String>) -> Self {
+        Self { message: message.into(), offset }
+    }
+}
+
+impl fmt::Display for MarkupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Splits a line into styled text and waits.
+///
+/// Tags are `{b}`, `{i}`, `{color=#rgb}`, `{size=N}` and `{speed=N}`, each
+/// closed by `{/name}`, plus the standalone `{w=seconds}`. `{{` and `}}` are
+/// literal braces.
+pub fn parse(src: &str) -> Result<Vec<Piece>, MarkupError> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut style = SpanStyle::default();
+    // Open tags, innermost last, with the style to go back to when each closes
+    let mut open: Vec<(&str, usize, SpanStyle)> = Vec::new();
+
+    let mut chars = src.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        match c {
+            '{' if chars.peek().is_some_and(|&(_, next)| next == '{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek().is_some_and(|&(_, next)| next == '}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let Some(len) = src[offset..].find('}') else {
+                    return Err(MarkupError::at(offset, "unclosed `{`"));
+                };
+                let tag = &src[offset + 1..offset + len];
+                while chars.peek().is_some_and(|&(i, _)| i <= offset + len) {
+                    chars.next();
+                }
+
+                // A `{$name}` placeholder, only seen when checking a line as
+                // written; it is filled in before the line is shown
+                if tag.starts_with('$') {
+                    text.push_str(&src[offset..=offset + len]);
+                    continue;
+                }
+
+                flush(&mut pieces, &mut text, &style);
+
+                if let Some(name) = tag.strip_prefix('/') {
+                    match open.pop() {
+                        Some((opened, _, previous)) if opened == name => style = previous,
+                        Some((opened, _, _)) => {
+                            return Err(MarkupError::at(
+                                offset,
+                                format!("`{{/{}}}` closes `{{{}}}`", name, opened),
+                            ));
+                        }
+                        None => {
+                            return Err(MarkupError::at(
+                                offset,
+                                format!("`{{/{}}}` without an opening tag", name),
+                            ));
+                        }
+                    }
+                    continue;
+                }
+
+                let (name, value) = match tag.split_once('=') {
+                    Some((name, value)) => (name.trim(), Some(value.trim())),
+                    None => (tag.trim(), None),
+                };
+
+                if name == "w" {
+                    pieces.push(Piece::Wait(number(offset, name, value, 0.0)?));
+                    continue;
+                }
+
+                let previous = style.clone();
+                match name {
+                    "b" => {
+                        no_value(offset, name, value)?;
+                        style.bold = true;
+                    }
+                    "i" => {
+                        no_value(offset, name, value)?;
+                        style.italic = true;
+                    }
+                    "color" => {
+                        let value = value.ok_or_else(|| needs_value(offset, name))?;
+                        let color = Srgba::hex(value).map_err(|_| {
+                            MarkupError::at(offset, format!("invalid color `{}`", value))
+                        })?;
+                        style.color = Some(color.into());
+                    }
+                    "size" => style.size = Some(number(offset, name, value, f32::MIN_POSITIVE)?),
+                    "speed" => style.speed = Some(number(offset, name, value, f32::MIN_POSITIVE)?),
+                    _ => return Err(MarkupError::at(offset, format!("unknown tag `{{{}}}`", tag))),
+                }
+                open.push((name, offset, previous));
+            }
+            c => text.push(c),
+        }
+    }
+
+    if let Some((name, offset, _)) = open.pop() {
+        return Err(MarkupError::at(offset, format!("`{{{}}}` is never closed", name)));
+    }
+
+    flush(&mut pieces, &mut text, &style);
+    Ok(pieces)
+}
+
+/// The text of a line with its tags removed, or the line as written if its
+/// markup is broken.
+pub fn strip(src: &str) -> String {
+    match parse(src) {
+        Ok(pieces) => pieces
+            .iter()
+            .filter_map(|piece| match piece {
+                Piece::Text { text, .. } => Some(text.as_str()),
+                Piece::Wait(_) => None,
+            })
+            .collect(),
+        Err(_) => src.to_string(),
+    }
+}
+
+fn flush(pieces: &mut Vec<Piece>, text: &mut String, style: &SpanStyle) {
+    if !text.is_empty() {
+        pieces.push(Piece::Text { text: std::mem::take(text), style: style.clone() });
+    }
+}
+
+fn no_value(offset: usize, name: &str, value: Option<&str>) -> Result<(), MarkupError> {
+    match value {
+        Some(_) => Err(MarkupError::at(offset, format!("`{{{}}}` takes no value", name))),
+        None => Ok(()),
+    }
+}
+
+fn needs_value(offset: usize, name: &str) -> MarkupError {
+    MarkupError::at(offset, format!("`{{{}}}` needs a value, as in `{{{}=...}}`", name, name))
+}
+
+/// Parses a tag's numeric value, which must be at least `min`.
+fn number(offset: usize, name: &str, value: Option<&str>, min: f32) -> Result<f32, MarkupError> {
+    let value = value.ok_or_else(|| needs_value(offset, name))?;
+    match value.parse::<f32>() {
+        Ok(number) if number >= min && number.is_finite() => Ok(number),
+        _ => Err(MarkupError::at(offset, format!("invalid `{{{}}}` value `{}`", name, value))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str, style: SpanStyle) -> Piece {
+        Piece::Text { text: text.to_string(), style }
+    }
+
+    #[test]
+    fn nests_styles_and_restores_them() {
+        let pieces = parse("a{b}b{color=#ff0}c{/color}d{/b}e").unwrap();
+        let bold = SpanStyle { bold: true, ..default() };
+        let yellow = SpanStyle { color: Some(Srgba::hex("ff0").unwrap().into()), ..bold.clone() };
+
+        assert_eq!(
+            pieces,
+            vec![
+                text("a", SpanStyle::default()),
+                text("b", bold.clone()),
+                text("c", yellow),
+                text("d", bold),
+                text("e", SpanStyle::default()),
+            ],
+        );
+    }
+
+    #[test]
+    fn waits_and_literal_braces() {
+        let pieces = parse("Well{w=0.5}... {{no}}").unwrap();
+        assert_eq!(
+            pieces,
+            vec![
+                text("Well", SpanStyle::default()),
+                Piece::Wait(0.5),
+                text("... {no}", SpanStyle::default()),
+            ],
+        );
+    }
+
+    #[test]
+    fn reports_bad_tags_where_they_start() {
+        let err = |src: &str| parse(src).unwrap_err();
+
+        assert_eq!(err("hi {blink}x{/blink}"), MarkupError::at(3, "unknown tag `{blink}`"));
+        assert_eq!(err("{i}hi"), MarkupError::at(0, "`{i}` is never closed"));
+        assert_eq!(err("{b}{i}x{/b}{/i}"), MarkupError::at(7, "`{/b}` closes `{i}`"));
+        assert_eq!(err("x{/b}"), MarkupError::at(1, "`{/b}` without an opening tag"));
+        assert_eq!(err("oops {b"), MarkupError::at(5, "unclosed `{`"));
+        assert_eq!(err("{speed=fast}x{/speed}"), MarkupError::at(0, "invalid `{speed}` value `fast`"));
+    }
+}
@@ -1,3 +1,9 @@
 pub mod runner;
 pub mod loader;
-pub mod expr;
\ No newline at end of file
+pub mod expr;
+pub mod error;
+pub mod asset;
+pub mod skip;
+pub mod auto;
+pub mod markup;
+pub mod interpolate;
\ No newline at end of file
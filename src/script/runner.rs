@@ -1,13 +1,24 @@
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-use crate::ui::dialogue::DialogueState;
+use crate::ui::dialogue::{DialogueState, Typewriter};
 use crate::ui::choices::ChoiceRequest;
-use crate::vars::store::{VarStore, Value};
-use crate::script::expr::eval;
+use crate::ui::history::{DialogueHistory, HistoryEntry, HistoryScreen};
+use crate::script::skip::SeenLines;
+use crate::vars::store::VarStore;
+use crate::script::expr::Expr;
+use crate::script::loader::{Script, is_generated_label};
+use crate::script::interpolate;
+use crate::save::autosave::Autosaves;
+use crate::save::metadata::PlayTime;
+use crate::save::rollback::Rollback;
+use crate::save::save_system::capture;
 
 use crate::scene::characters::{
     CharacterManager,
+    CharacterDefs,
     show_character,
     hide_character,
     TransformParams,
@@ -21,12 +32,15 @@ use crate::scene::background::{
 
 use crate::audio::{
     MusicManager,
+    VoiceManager,
     play_music,
     stop_music,
+    play_voice,
+    stop_voice,
     play_sfx,
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Instruction {
     Say {
         speaker: Option<String>,
@@ -35,13 +49,20 @@ pub enum Instruction {
     Label(String),
     JumpLabel(String),
 
+    Call(String),
+    Return,
+    End,
+
+    /// Writes an autosave once the runner reaches it.
+    Autosave,
+
     SetVar {
         name: String,
-        expression: String,
+        expression: Expr,
     },
 
     IfJump {
-        condition: String,
+        condition: Expr,
         target: String,
     },
 
@@ -49,7 +70,8 @@ pub enum Instruction {
 
     ShowCharacter {
         name: String,
-        expression: String,
+        /// `None` shows the character's default expression.
+        expression: Option<String>,
         params: TransformParams,
     },
 
@@ -63,14 +85,41 @@ pub enum Instruction {
     MusicPlay(String),
     MusicStop,
     SfxPlay(String),
+    /// Voice clip for the next `say`.
+    VoicePlay(String),
+}
+
+/// A place in the script recorded relative to the nearest written label
+/// before it, so it still means the same thing after lines are added above.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ScriptPosition {
+    /// `None` when the position comes before the first label.
+    pub label: Option<String>,
+    pub offset: usize,
 }
 
+/// Nested `call`s deeper than this are treated as runaway recursion.
+pub const MAX_CALL_DEPTH: usize = 256;
+
+/// Default for `ScriptRunner::max_steps_per_frame`.
+pub const DEFAULT_MAX_STEPS_PER_FRAME: usize = 1000;
+
 #[derive(Resource)]
 pub struct ScriptRunner {
     pub instructions: Vec<Instruction>,
     pub labels: HashMap<String, usize>,
     pub ip: usize,
     pub waiting: bool,
+    /// Return addresses pushed by `call`, popped by `return`.
+    pub call_stack: Vec<usize>,
+    /// Upper bound on instructions executed in one frame, so a loop that
+    /// never reaches a `say` or `choice` can't freeze the game.
+    pub max_steps_per_frame: usize,
+    /// Voice clip waiting for the next `say`.
+    pub pending_voice: Option<String>,
+    /// The instruction executed before the current one, telling a label
+    /// read on to from one jumped back to.
+    pub last_ip: Option<usize>,
 }
 
 impl Default for ScriptRunner {
@@ -80,20 +129,155 @@ impl Default for ScriptRunner {
             labels: HashMap::new(),
             ip: 0,
             waiting: false,
+            call_stack: Vec::new(),
+            max_steps_per_frame: DEFAULT_MAX_STEPS_PER_FRAME,
+            pending_voice: None,
+            last_ip: None,
         }
     }
 }
 
 impl ScriptRunner {
-    pub fn rebuild_labels(&mut self) {
-        self.labels.clear();
-        for (i, instr) in self.instructions.iter().enumerate() {
-            if let Instruction::Label(name) = instr {
-                self.labels.insert(name.clone(), i);
-            }
+    pub fn load(&mut self, script: Script) {
+        self.instructions = script.instructions;
+        self.labels = script.labels;
+        self.ip = 0;
+        self.waiting = false;
+        self.call_stack.clear();
+        self.last_ip = None;
+    }
+
+    /// Swaps in an edited version of the script, resuming at the start of the
+    /// label the runner was in, or the nearest surviving label before it.
+    pub fn reload(&mut self, script: Script) {
+        let old = std::mem::replace(&mut self.instructions, script.instructions);
+        self.labels = script.labels;
+
+        // A line or choice being waited on belongs to the label it is in,
+        // even when it is the last thing before the next one
+        let current = if self.waiting { self.ip.saturating_sub(1) } else { self.ip };
+        let resume = nearest_label(&old, current, &self.labels);
+        self.ip = resume.map_or(0, |(_, pos)| pos);
+        self.waiting = false;
+        self.last_ip = None;
+
+        // Return addresses keep their offset from their label when it still
+        // fits before the next label
+        let call_stack = std::mem::take(&mut self.call_stack);
+        self.call_stack = call_stack
+            .into_iter()
+            .map(|ip| match nearest_label(&old, ip, &self.labels) {
+                Some((offset, pos)) if self.is_within_label(pos, offset) => pos + offset,
+                Some((_, pos)) => pos,
+                None => ip.min(self.instructions.len()),
+            })
+            .collect();
+    }
+
+    /// Whether `offset` instructions past the label at `label_pos` is still
+    /// inside that label, before the next written one.
+    fn is_within_label(&self, label_pos: usize, offset: usize) -> bool {
+        let end = label_pos + offset;
+        end < self.instructions.len()
+            && !self.instructions[label_pos + 1..=end].iter().any(|instr| {
+                matches!(instr, Instruction::Label(name) if !is_generated_label(name))
+            })
+    }
+
+    /// Describes `ip` relative to the nearest written label at or before it.
+    pub fn position_of(&self, ip: usize) -> ScriptPosition {
+        let label = (0..=ip)
+            .rev()
+            .filter(|&i| i < self.instructions.len())
+            .find_map(|i| match &self.instructions[i] {
+                Instruction::Label(name) if !is_generated_label(name) => Some((i, name)),
+                _ => None,
+            });
+
+        match label {
+            Some((i, name)) => ScriptPosition { label: Some(name.clone()), offset: ip - i },
+            None => ScriptPosition { label: None, offset: ip },
         }
     }
 
+    /// Finds `position` in the current script. The second value is `false`
+    /// when the offset no longer fits inside its label and the position fell
+    /// back to the label itself. Returns `None` if the label no longer exists.
+    pub fn resolve_position(&self, position: &ScriptPosition) -> Option<(usize, bool)> {
+        let Some(label) = &position.label else {
+            return (position.offset <= self.instructions.len()).then_some((position.offset, true));
+        };
+
+        let &pos = self.labels.get(label)?;
+        if self.is_within_label(pos, position.offset) {
+            Some((pos + position.offset, true))
+        } else {
+            Some((pos, false))
+        }
+    }
+
+    /// Where a save anchors the runner: the `say` or `choice` it is waiting
+    /// on, otherwise the next instruction to run. Anchoring at the line
+    /// itself means lines inserted after it aren't skipped on load.
+    pub fn resume_position(&self) -> ScriptPosition {
+        if self.waiting && self.ip > 0 {
+            self.position_of(self.ip - 1)
+        } else {
+            self.position_of(self.ip)
+        }
+    }
+
+    /// Moves the runner to a position from `resume_position`, waiting on the
+    /// line there again if `waiting`. Returns `false`, leaving the runner as
+    /// it was, if the position's label no longer exists.
+    pub fn resume_at(&mut self, position: &ScriptPosition, waiting: bool) -> bool {
+        let Some((ip, exact)) = self.resolve_position(position) else {
+            return false;
+        };
+
+        // If the script changed under the save, replay from the label rather
+        // than wait on a line that may no longer be there
+        if waiting && exact {
+            self.ip = ip + 1;
+            self.waiting = true;
+        } else {
+            self.ip = ip;
+            self.waiting = false;
+        }
+        self.last_ip = None;
+        true
+    }
+
+    /// Whether the runner came to its current instruction going forward, by
+    /// reading on or jumping ahead, rather than by jumping back to it.
+    pub fn moved_forward(&self) -> bool {
+        self.last_ip.is_none_or(|last| last < self.ip)
+    }
+
+    /// Whether the runner is blocked on a `choice`, which only a click on one
+    /// of its options can answer.
+    pub fn waiting_on_choice(&self) -> bool {
+        self.waiting
+            && self.ip > 0
+            && matches!(self.instructions.get(self.ip - 1), Some(Instruction::Choice(_)))
+    }
+
+    /// Position of the `say` the runner is showing and waiting on, if any.
+    pub fn current_line(&self) -> Option<ScriptPosition> {
+        let waiting_on_say = self.waiting
+            && self.ip > 0
+            && matches!(self.instructions.get(self.ip - 1), Some(Instruction::Say { .. }));
+
+        waiting_on_say.then(|| self.position_of(self.ip - 1))
+    }
+
+    /// Stops the script after an unrecoverable error.
+    pub fn halt(&mut self) {
+        self.ip = self.instructions.len();
+        self.waiting = false;
+        self.call_stack.clear();
+    }
+
     pub fn jump_to_label(&mut self, label: &str) {
         if let Some(&pos) = self.labels.get(label) {
             self.ip = pos;
@@ -102,111 +286,381 @@ impl ScriptRunner {
     }
 }
 
-fn set_number(vars: &mut VarStore, name: &str, value: f64) {
-    vars.set(name, Value::Float(value as f32));
+/// Walks back from `ip` in `old` to the nearest label that still exists in
+/// `labels`, returning the offset of `ip` from that label and the label's new
+/// position.
+fn nearest_label(
+    old: &[Instruction],
+    ip: usize,
+    labels: &HashMap<String, usize>,
+) -> Option<(usize, usize)> {
+    let start = ip.min(old.len().saturating_sub(1));
+
+    (0..=start).rev().find_map(|i| match &old[i] {
+        Instruction::Label(name) if !is_generated_label(name) => {
+            labels.get(name).map(|&pos| (ip - i, pos))
+        }
+        _ => None,
+    })
 }
 
+/// Everything in the world a script instruction can touch.
+#[derive(SystemParam)]
+pub struct ScriptContext<'w, 's> {
+    pub commands: Commands<'w, 's>,
+    pub asset_server: Res<'w, AssetServer>,
+    pub dialogue: ResMut<'w, DialogueState>,
+    pub vars: ResMut<'w, VarStore>,
+    pub choice_req: ResMut<'w, ChoiceRequest>,
+    pub characters: ResMut<'w, CharacterManager>,
+    pub character_defs: Res<'w, CharacterDefs>,
+    pub backgrounds: ResMut<'w, BackgroundManager>,
+    pub music: ResMut<'w, MusicManager>,
+    pub voice: ResMut<'w, VoiceManager>,
+    pub history: ResMut<'w, DialogueHistory>,
+    pub play_time: ResMut<'w, PlayTime>,
+    pub autosaves: ResMut<'w, Autosaves>,
+    pub rollback: ResMut<'w, Rollback>,
+}
+
+/// Runs instructions until one has to wait for the player (`say`, `choice`),
+/// so everything between two lines of dialogue happens in a single frame.
 pub fn script_runner_system(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
     mut runner: ResMut<ScriptRunner>,
-    mut dialogue: ResMut<DialogueState>,
-    mut vars: ResMut<VarStore>,
-    mut choice_req: ResMut<ChoiceRequest>,
-    mut characters: ResMut<CharacterManager>,
-    mut backgrounds: ResMut<BackgroundManager>,
-    mut music: ResMut<MusicManager>,
+    mut ctx: ScriptContext,
 ) {
-    if runner.waiting || runner.ip >= runner.instructions.len() {
-        return;
+    let mut steps = 0;
+
+    while !runner.waiting && runner.ip < runner.instructions.len() {
+        if steps >= runner.max_steps_per_frame {
+            warn!(
+                "script ran {} instructions without waiting for input; possible infinite loop near instruction {}",
+                steps, runner.ip,
+            );
+            break;
+        }
+
+        execute(&mut runner, &mut ctx);
+        steps += 1;
     }
+}
 
+/// Executes the instruction at `runner.ip` and moves to the next one.
+fn execute(runner: &mut ScriptRunner, ctx: &mut ScriptContext) {
     let instruction = runner.instructions[runner.ip].clone();
 
+    // Autosave when a chapter starts (a written label reached going forward
+    // outside of a `call`, so not each time a loop jumps back to a hub), when
+    // a choice is shown, and wherever the script asks to
+    let autosave = match &instruction {
+        Instruction::Label(name) => {
+            !is_generated_label(name) && runner.call_stack.is_empty() && runner.moved_forward()
+        }
+        Instruction::Choice(_) | Instruction::Autosave => true,
+        _ => false,
+    };
+    runner.last_ip = Some(runner.ip);
+
     match instruction {
         Instruction::Say { speaker, text } => {
-            dialogue.speaker = speaker;
-            dialogue.current_line = Some(text);
+            // A defined character speaks under its display name
+            let def = speaker.as_deref().and_then(|id| ctx.character_defs.get(id));
+            let name = match def.and_then(|def| def.name.as_ref()) {
+                Some(name) => Some(name.clone()),
+                None => speaker.clone(),
+            };
+            let blip = def.and_then(|def| def.blip.clone());
+
+            // Variables show their values as of when the line is reached
+            let name = name.map(|name| interpolate::resolve(&name, &ctx.vars));
+            let text = interpolate::resolve_markup(&text, &ctx.vars);
+
+            let voice = runner.pending_voice.take();
+            match &voice {
+                Some(path) => play_voice(&mut ctx.commands, &ctx.asset_server, &mut ctx.voice, path),
+                None => {
+                    stop_voice(&mut ctx.commands, &mut ctx.voice);
+                    if let Some(blip) = blip {
+                        play_sfx(&mut ctx.commands, &ctx.asset_server, blip);
+                    }
+                }
+            }
+
+            ctx.history.push(HistoryEntry {
+                speaker: name.clone(),
+                character: speaker.clone(),
+                text: text.clone(),
+                voice,
+            });
+
+            ctx.dialogue.speaker = name;
+            ctx.dialogue.character = speaker;
+            ctx.dialogue.current_line = Some(text);
             runner.waiting = true;
         }
 
-        Instruction::Label(_) => {}
+        Instruction::Label(_) | Instruction::Autosave => {}
 
         Instruction::JumpLabel(label) => {
             runner.jump_to_label(&label);
             return;
         }
 
+        Instruction::Call(label) => {
+            if runner.call_stack.len() >= MAX_CALL_DEPTH {
+                error!("call {}: call stack overflow (depth {})", label, MAX_CALL_DEPTH);
+                runner.halt();
+                return;
+            }
+
+            let return_ip = runner.ip + 1;
+            runner.call_stack.push(return_ip);
+            runner.jump_to_label(&label);
+            return;
+        }
+
+        Instruction::Return => {
+            let Some(return_ip) = runner.call_stack.pop() else {
+                error!("return with an empty call stack");
+                runner.halt();
+                return;
+            };
+
+            runner.ip = return_ip;
+            return;
+        }
+
+        Instruction::End => {
+            runner.halt();
+            return;
+        }
+
         Instruction::Choice(options) => {
-            choice_req.options = Some(options);
+            ctx.choice_req.options = Some(options);
             runner.waiting = true;
         }
 
         Instruction::SetVar { name, expression } => {
-            let value = eval(&expression, &vars);
-            set_number(&mut vars, &name, value);
+            match expression.eval(&ctx.vars) {
+                Ok(value) => ctx.vars.set(&name, value),
+                Err(err) => error!("set {}: {}", name, err),
+            }
         }
 
         Instruction::IfJump { condition, target } => {
-            if eval(&condition, &vars) != 0.0 {
-                runner.jump_to_label(&target);
-                return;
+            match condition.holds(&ctx.vars) {
+                Ok(true) => {
+                    runner.jump_to_label(&target);
+                    return;
+                }
+                Ok(false) => {}
+                Err(err) => error!("if condition: {}", err),
             }
         }
 
         Instruction::ShowCharacter { name, expression, params } => {
-            show_character(
-                &mut commands,
-                &asset_server,
-                &mut characters,
-                name,
-                expression,
-                params,
-            );
+            let expression = expression.or_else(|| {
+                ctx.character_defs.get(&name).and_then(|def| def.expression.clone())
+            });
+
+            match expression {
+                Some(expression) => show_character(
+                    &mut ctx.commands,
+                    &ctx.asset_server,
+                    &mut ctx.characters,
+                    &ctx.character_defs,
+                    name,
+                    expression,
+                    params,
+                ),
+                None => error!("show {}: no expression given and no default defined", name),
+            }
         }
 
         Instruction::HideCharacter { name } => {
-            hide_character(&mut commands, &mut characters, &name);
+            hide_character(&mut ctx.commands, &mut ctx.characters, &name);
         }
 
         Instruction::BgImage(path) => {
             set_background_image(
-                &mut commands,
-                &asset_server,
-                &mut backgrounds,
+                &mut ctx.commands,
+                &ctx.asset_server,
+                &mut ctx.backgrounds,
                 path,
             );
         }
 
         Instruction::BgColor(color) => {
             set_background_color(
-                &mut commands,
-                &mut backgrounds,
+                &mut ctx.commands,
+                &mut ctx.backgrounds,
                 color,
             );
         }
 
         Instruction::MusicPlay(path) => {
-            play_music(&mut commands, &asset_server, &mut music, path);
+            play_music(&mut ctx.commands, &ctx.asset_server, &mut ctx.music, path);
         }
 
         Instruction::MusicStop => {
-            stop_music(&mut commands, &mut music);
+            stop_music(&mut ctx.commands, &mut ctx.music);
         }
 
         Instruction::SfxPlay(path) => {
-            play_sfx(&mut commands, &asset_server, path);
+            play_sfx(&mut ctx.commands, &ctx.asset_server, path);
+        }
+
+        Instruction::VoicePlay(path) => {
+            runner.pending_voice = Some(path);
         }
     }
 
     runner.ip += 1;
+
+    // Taken after the instruction, so loading it doesn't autosave again
+    if autosave {
+        ctx.autosaves.pending = Some(capture(runner, ctx));
+    }
+
+    // Every line and choice the player stops at can be rolled back to
+    if runner.waiting {
+        let snapshot = capture(runner, ctx);
+        ctx.rollback.record(snapshot);
+    }
 }
 
+/// Space or a click finishes revealing the line on screen, and once it is
+/// whole, moves on to the next one.
 pub fn advance_dialogue(
     keyboard: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    history: Res<HistoryScreen>,
+    mut typewriter: ResMut<Typewriter>,
+    mut seen: ResMut<SeenLines>,
     mut runner: ResMut<ScriptRunner>,
 ) {
-    if keyboard.just_pressed(KeyCode::Space) {
-        runner.waiting = false;
+    let pressed = keyboard.just_pressed(KeyCode::Space) || mouse.just_pressed(MouseButton::Left);
+    if !pressed || runner.waiting_on_choice() || history.open {
+        return;
+    }
+
+    if typewriter.is_revealing() {
+        typewriter.complete();
+        return;
+    }
+
+    if let Some(line) = runner.current_line() {
+        seen.mark(line);
+    }
+    runner.waiting = false;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::loader::ScriptBuilder;
+
+    const SCRIPT: &str = "\
+label start
+say one
+say two
+label next
+say three
+";
+
+    fn runner(src: &str) -> ScriptRunner {
+        let mut builder = ScriptBuilder::new("test.vn");
+        while let Some(file) = builder.next_file() {
+            builder.add_file(&file, src);
+        }
+
+        let mut runner = ScriptRunner::default();
+        runner.load(builder.build().unwrap());
+        runner
+    }
+
+    fn say(text: &str) -> Instruction {
+        Instruction::Say { speaker: None, text: text.to_string() }
+    }
+
+    /// A runner showing "two", the last line before `label next`.
+    fn waiting_on_two() -> ScriptRunner {
+        let mut runner = runner(SCRIPT);
+        runner.ip = 3;
+        runner.waiting = true;
+        runner
+    }
+
+    #[test]
+    fn waiting_runner_is_anchored_at_its_line() {
+        let runner = waiting_on_two();
+        let anchor = ScriptPosition { label: Some("start".to_string()), offset: 2 };
+
+        // The next instruction belongs to the following label
+        assert_eq!(runner.position_of(runner.ip).label.as_deref(), Some("next"));
+        assert_eq!(runner.resume_position(), anchor);
+        assert_eq!(runner.current_line(), Some(anchor));
+    }
+
+    #[test]
+    fn resumes_on_the_same_line_after_lines_are_inserted() {
+        let position = waiting_on_two().resume_position();
+        let mut edited = runner(&SCRIPT.replace("say two\n", "say two\nsay inserted\n"));
+
+        assert_eq!(edited.resolve_position(&position), Some((2, true)));
+        assert!(edited.resume_at(&position, true));
+        assert!(edited.waiting);
+        assert_eq!(edited.instructions[edited.ip - 1], say("two"));
+        assert_eq!(edited.instructions[edited.ip], say("inserted"));
+    }
+
+    #[test]
+    fn replays_from_the_label_when_the_offset_no_longer_fits() {
+        let position = waiting_on_two().resume_position();
+        let mut edited = runner(&SCRIPT.replace("say one\n", ""));
+
+        assert_eq!(edited.resolve_position(&position), Some((0, false)));
+        assert!(edited.resume_at(&position, true));
+        assert_eq!(edited.ip, 0);
+        assert!(!edited.waiting);
+    }
+
+    #[test]
+    fn refuses_positions_whose_label_is_gone() {
+        let position = waiting_on_two().resume_position();
+        let mut edited = runner(&SCRIPT.replace("label start", "label intro"));
+
+        assert_eq!(edited.resolve_position(&position), None);
+        assert!(!edited.resume_at(&position, true));
+        assert_eq!(edited.ip, 0);
+    }
+
+    #[test]
+    fn runner_that_is_not_waiting_resumes_at_its_next_instruction() {
+        let mut playing = runner(SCRIPT);
+        playing.ip = 3;
+
+        let position = playing.resume_position();
+        assert_eq!(position, ScriptPosition { label: Some("next".to_string()), offset: 0 });
+
+        let mut edited = runner(&SCRIPT.replace("say two\n", "say two\nsay inserted\n"));
+        assert!(edited.resume_at(&position, false));
+        assert_eq!(edited.instructions[edited.ip], Instruction::Label("next".to_string()));
+    }
+
+    #[test]
+    fn only_labels_reached_going_forward_start_a_chapter() {
+        let mut runner = runner(SCRIPT);
+        assert!(runner.moved_forward());
+
+        // Reading on from `say two` into `label next`
+        runner.ip = 3;
+        runner.last_ip = Some(2);
+        assert!(runner.moved_forward());
+
+        // A loop jumping back to `label start` from `say three`
+        runner.ip = 0;
+        runner.last_ip = Some(4);
+        assert!(!runner.moved_forward());
     }
 }
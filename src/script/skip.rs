@@ -0,0 +1,137 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+use crate::save::save_system::{read_save_file, write_save_file};
+use crate::script::runner::{ScriptPosition, ScriptRunner};
+use crate::ui::history::HistoryScreen;
+
+/// File in the save directory holding the lines read so far.
+pub const SEEN_FILE: &str = "seen.ron";
+
+/// Every `say` the player has read past, in any playthrough.
+#[derive(Resource, Default)]
+pub struct SeenLines {
+    pub lines: HashSet<ScriptPosition>,
+    /// Set when a line is first read and the set has yet to be written out.
+    pub changed: bool,
+}
+
+impl SeenLines {
+    pub fn is_seen(&self, line: &ScriptPosition) -> bool {
+        self.lines.contains(line)
+    }
+
+    pub fn mark(&mut self, line: ScriptPosition) {
+        if self.lines.insert(line) {
+            self.changed = true;
+        }
+    }
+}
+
+/// Fast-forwarding through dialogue. It runs while toggled on or while Ctrl
+/// is held, and turns itself off at a choice or at an unread line.
+#[derive(Resource, Default)]
+pub struct SkipMode {
+    /// Toggled with Tab.
+    pub active: bool,
+    /// Skip lines the player has never read as well.
+    pub skip_unread: bool,
+}
+
+pub fn load_seen_lines(mut seen: ResMut<SeenLines>) {
+    match read_save_file(SEEN_FILE) {
+        Ok(Some(lines)) => seen.lines = lines,
+        // Nothing has been read yet
+        Ok(None) => {}
+        Err(err) => error!("failed to read seen lines: {}", err),
+    }
+}
+
+/// Writes the seen lines out whenever a new one is read.
+pub fn save_seen_lines(mut seen: ResMut<SeenLines>) {
+    if !seen.changed {
+        return;
+    }
+    seen.changed = false;
+
+    if let Err(err) = write_save_file(SEEN_FILE, &seen.lines) {
+        error!("failed to write seen lines: {}", err);
+    }
+}
+
+/// Advances one line per frame while skipping.
+pub fn skip_system(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    history: Res<HistoryScreen>,
+    mut skip: ResMut<SkipMode>,
+    mut seen: ResMut<SeenLines>,
+    mut runner: ResMut<ScriptRunner>,
+) {
+    if keyboard.just_pressed(KeyCode::Tab) {
+        skip.active = !skip.active;
+    }
+
+    let held = keyboard.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    if !(skip.active || held) || history.open {
+        return;
+    }
+
+    if runner.waiting_on_choice() {
+        skip.active = false;
+        return;
+    }
+
+    let Some(line) = runner.current_line() else {
+        return;
+    };
+
+    if !skip.skip_unread && !seen.is_seen(&line) {
+        skip.active = false;
+        return;
+    }
+
+    seen.mark(line);
+    runner.waiting = false;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::runner::Instruction;
+
+    /// An app showing a line the player has already read.
+    fn app() -> App {
+        let runner = ScriptRunner {
+            instructions: vec![Instruction::Say { speaker: None, text: "Hello.".to_string() }],
+            ip: 1,
+            waiting: true,
+            ..default()
+        };
+        let mut seen = SeenLines::default();
+        seen.mark(runner.current_line().unwrap());
+
+        let mut app = App::new();
+        app.init_resource::<ButtonInput<KeyCode>>()
+            .init_resource::<HistoryScreen>()
+            .init_resource::<SkipMode>()
+            .insert_resource(seen)
+            .insert_resource(runner)
+            .add_systems(Update, skip_system);
+        app
+    }
+
+    fn hold(app: &mut App, keys: &[KeyCode]) -> bool {
+        let mut keyboard = app.world_mut().resource_mut::<ButtonInput<KeyCode>>();
+        for &key in keys {
+            keyboard.press(key);
+        }
+        app.update();
+        app.world().resource::<ScriptRunner>().waiting
+    }
+
+    #[test]
+    fn holding_ctrl_skips_read_lines() {
+        assert!(!hold(&mut app(), &[KeyCode::ControlLeft]));
+    }
+}
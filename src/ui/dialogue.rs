@@ -1,11 +1,107 @@
 use bevy::prelude::*;
 
+use crate::scene::characters::CharacterDefs;
+use crate::script::markup::{self, Piece, SpanStyle};
+
 #[derive(Resource, Default)]
 pub struct DialogueState {
+    /// Name shown above the line.
     pub speaker: Option<String>,
+    /// ID of the defined character speaking, for their colors.
+    pub character: Option<String>,
     pub current_line: Option<String>,
 }
 
+/// Color of the speaker's name, for characters without a `name_color`.
+pub const SPEAKER_COLOR: Color = Color::srgb(0.9, 0.9, 0.4);
+
+/// Color of dialogue text, for characters without a `text_color`.
+pub const TEXT_COLOR: Color = Color::WHITE;
+
+/// Default for `Typewriter::chars_per_second`.
+pub const DEFAULT_TEXT_SPEED: f32 = 40.0;
+
+/// Font faces for styled dialogue text, loaded with the dialogue box.
+#[derive(Resource, Default)]
+pub struct DialogueFonts {
+    /// Used for `{i}` text.
+    pub italic: Handle<Font>,
+}
+
+/// How much of the current line is on screen.
+#[derive(Resource)]
+pub struct Typewriter {
+    /// Characters revealed per second; zero or less shows lines whole.
+    pub chars_per_second: f32,
+    /// Seconds since the current line started revealing.
+    elapsed: f32,
+    /// When each character of the current line appears, in seconds from the
+    /// start of the line, taking `{speed}` and `{w}` tags into account.
+    schedule: Vec<f32>,
+}
+
+impl Default for Typewriter {
+    fn default() -> Self {
+        Self {
+            chars_per_second: DEFAULT_TEXT_SPEED,
+            elapsed: 0.0,
+            schedule: Vec::new(),
+        }
+    }
+}
+
+impl Typewriter {
+    /// Whether part of the current line is still hidden.
+    pub fn is_revealing(&self) -> bool {
+        self.visible() < self.schedule.len()
+    }
+
+    /// Shows the rest of the current line at once.
+    pub fn complete(&mut self) {
+        self.elapsed = self.schedule.last().copied().unwrap_or(0.0);
+    }
+
+    /// Number of characters of the current line on screen.
+    pub fn visible(&self) -> usize {
+        self.schedule.partition_point(|&time| time <= self.elapsed)
+    }
+
+    fn start(&mut self, pieces: &[Piece]) {
+        self.elapsed = 0.0;
+        self.schedule.clear();
+
+        let mut time = 0.0;
+        for piece in pieces {
+            match piece {
+                Piece::Wait(seconds) => time += seconds,
+                Piece::Text { text, style } => {
+                    let speed = style.speed.unwrap_or(self.chars_per_second);
+                    let start = time;
+                    for (i, _) in text.chars().enumerate() {
+                        if self.chars_per_second > 0.0 {
+                            time = start + (i + 1) as f32 / speed;
+                        }
+                        self.schedule.push(time);
+                    }
+                }
+            }
+        }
+
+        if self.chars_per_second <= 0.0 {
+            self.complete();
+        }
+    }
+}
+
+/// One styled run of the current line, as a `TextSpan` under `DialogueText`.
+#[derive(Component)]
+pub struct DialogueSpan {
+    /// The run's full text, revealed into its `TextSpan` bit by bit.
+    pub text: String,
+    /// Characters of the line before this run.
+    pub start: usize,
+}
+
 #[derive(Component)]
 pub struct DialogueText;
 
@@ -20,6 +116,9 @@ pub fn setup_dialogue_ui(
     asset_server: Res<AssetServer>,
 ) {
     let font = asset_server.load("fonts/main.ttf");
+    commands.insert_resource(DialogueFonts {
+        italic: asset_server.load("fonts/main-italic.ttf"),
+    });
 
     commands
         .spawn((
@@ -45,7 +144,7 @@ pub fn setup_dialogue_ui(
                     font_size: 26.0,
                     ..default()
                 },
-                TextColor(Color::srgb(0.9, 0.9, 0.4)),
+                TextColor(SPEAKER_COLOR),
                 SpeakerText,
             ));
 
@@ -57,32 +156,227 @@ pub fn setup_dialogue_ui(
                     font_size: 32.0,
                     ..default()
                 },
-                TextColor(Color::WHITE),
+                TextColor(TEXT_COLOR),
                 DialogueText,
             ));
         });
 }
 
-pub fn update_dialogue_text(
+/// Lays out a new line as one `TextSpan` per styled run and starts
+/// revealing it.
+pub fn start_dialogue_line(
+    mut commands: Commands,
+    fonts: Res<DialogueFonts>,
+    character_defs: Res<CharacterDefs>,
     dialogue: Res<DialogueState>,
-    mut text_query: Query<&mut Text, With<DialogueText>>,
-    mut speaker_query: Query<&mut Text, (With<SpeakerText>, Without<DialogueText>)>,
+    mut typewriter: ResMut<Typewriter>,
+    text_query: Query<(Entity, &TextFont), With<DialogueText>>,
+    mut speaker_query: Query<(&mut Text, &mut TextColor), With<SpeakerText>>,
 ) {
     if !dialogue.is_changed() {
         return;
     }
 
-    for mut text in &mut text_query {
-        text.0 = dialogue
-            .current_line
-            .clone()
-            .unwrap_or_default();
-    }
+    let def = dialogue.character.as_deref().and_then(|id| character_defs.get(id));
+    let name_color = def.and_then(|def| def.name_color).unwrap_or(SPEAKER_COLOR);
+    let text_color = def.and_then(|def| def.text_color).unwrap_or(TEXT_COLOR);
 
-    for mut speaker in &mut speaker_query {
+    for (mut speaker, mut color) in &mut speaker_query {
         speaker.0 = dialogue
             .speaker
             .clone()
             .unwrap_or_default();
+        color.0 = name_color;
+    }
+
+    let line = dialogue.current_line.as_deref().unwrap_or_default();
+    // Scripts are checked when they load, but a save can still hold a line
+    // from an older version of one
+    let pieces = markup::parse(line).unwrap_or_else(|_| {
+        vec![Piece::Text { text: line.to_string(), style: SpanStyle::default() }]
+    });
+
+    typewriter.start(&pieces);
+
+    for (entity, font) in &text_query {
+        commands.entity(entity).despawn_children().with_children(|parent| {
+            let mut start = 0;
+
+            for piece in &pieces {
+                let Piece::Text { text, style } = piece else {
+                    continue;
+                };
+
+                let mut span_font = font.clone();
+                if style.italic {
+                    span_font.font = fonts.italic.clone();
+                }
+                if style.bold {
+                    span_font.weight = FontWeight::BOLD;
+                }
+                if let Some(size) = style.size {
+                    span_font.font_size = size;
+                }
+
+                parent.spawn((
+                    TextSpan::default(),
+                    span_font,
+                    TextColor(style.color.unwrap_or(text_color)),
+                    DialogueSpan { text: text.clone(), start },
+                ));
+
+                start += text.chars().count();
+            }
+        });
+    }
+}
+
+/// Reveals the current line a few characters at a time.
+pub fn reveal_dialogue_text(
+    time: Res<Time>,
+    dialogue: Res<DialogueState>,
+    mut typewriter: ResMut<Typewriter>,
+    mut span_query: Query<(&DialogueSpan, &mut TextSpan)>,
+) {
+    // A line starts with nothing shown, whatever the frame time
+    if !dialogue.is_changed() && typewriter.is_revealing() {
+        typewriter.elapsed += time.delta_secs();
+    }
+
+    let visible = typewriter.visible();
+    for (span, mut text) in &mut span_query {
+        let shown = visible.saturating_sub(span.start);
+        if text.0.chars().count() != shown.min(span.text.chars().count()) {
+            text.0 = span.text.chars().take(shown).collect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::script::runner::{advance_dialogue, Instruction, ScriptRunner};
+    use crate::script::skip::SeenLines;
+    use crate::ui::history::HistoryScreen;
+
+    const LINE: &str = "Hello there";
+
+    /// An app that is showing `line` and waiting on it, with a clock that only
+    /// moves when `step` says so.
+    fn app(line: &str, chars_per_second: f32) -> App {
+        let mut app = App::new();
+        app.init_resource::<Time>()
+            .init_resource::<ButtonInput<KeyCode>>()
+            .init_resource::<ButtonInput<MouseButton>>()
+            .init_resource::<HistoryScreen>()
+            .init_resource::<SeenLines>()
+            .init_resource::<DialogueFonts>()
+            .init_resource::<CharacterDefs>()
+            .insert_resource(Typewriter { chars_per_second, ..default() })
+            .insert_resource(DialogueState {
+                speaker: None,
+                character: None,
+                current_line: Some(line.to_string()),
+            })
+            .insert_resource(ScriptRunner {
+                instructions: vec![Instruction::Say { speaker: None, text: line.to_string() }],
+                ip: 1,
+                waiting: true,
+                ..default()
+            })
+            .add_systems(
+                Update,
+                (advance_dialogue, start_dialogue_line, reveal_dialogue_text).chain(),
+            );
+
+        app.world_mut().spawn((Text::new(""), TextFont::default(), TextColor::WHITE, DialogueText));
+        app
+    }
+
+    fn step(app: &mut App, seconds: f32) {
+        app.world_mut().resource_mut::<Time>().advance_by(Duration::from_secs_f32(seconds));
+        app.update();
+    }
+
+    fn press_space(app: &mut App) {
+        app.world_mut().resource_mut::<ButtonInput<KeyCode>>().press(KeyCode::Space);
+        step(app, 0.0);
+
+        let mut keyboard = app.world_mut().resource_mut::<ButtonInput<KeyCode>>();
+        keyboard.release(KeyCode::Space);
+        keyboard.clear();
+    }
+
+    fn shown(app: &mut App) -> String {
+        let mut query = app.world_mut().query::<(&DialogueSpan, &TextSpan)>();
+        let mut spans: Vec<_> = query.iter(app.world()).collect();
+        spans.sort_by_key(|(span, _)| span.start);
+        spans.iter().map(|(_, text)| text.0.as_str()).collect()
+    }
+
+    #[test]
+    fn reveals_at_the_configured_rate() {
+        let mut app = app(LINE, 10.0);
+
+        step(&mut app, 0.0);
+        assert_eq!(shown(&mut app), "");
+
+        step(&mut app, 0.55);
+        assert_eq!(shown(&mut app), "Hello");
+
+        step(&mut app, 0.3);
+        assert_eq!(shown(&mut app), "Hello th");
+
+        step(&mut app, 10.0);
+        assert_eq!(shown(&mut app), LINE);
+        assert!(!app.world().resource::<Typewriter>().is_revealing());
+    }
+
+    #[test]
+    fn first_press_completes_and_second_advances() {
+        let mut app = app(LINE, 10.0);
+        step(&mut app, 0.0);
+        step(&mut app, 0.25);
+        assert_eq!(shown(&mut app), "He");
+
+        press_space(&mut app);
+        assert_eq!(shown(&mut app), LINE);
+        assert!(app.world().resource::<ScriptRunner>().waiting);
+
+        press_space(&mut app);
+        assert!(!app.world().resource::<ScriptRunner>().waiting);
+    }
+
+    #[test]
+    fn zero_speed_shows_lines_whole() {
+        let mut app = app(LINE, 0.0);
+
+        step(&mut app, 0.0);
+        assert_eq!(shown(&mut app), LINE);
+
+        press_space(&mut app);
+        assert!(!app.world().resource::<ScriptRunner>().waiting);
+    }
+
+    #[test]
+    fn speed_and_wait_tags_shape_the_reveal() {
+        let mut app = app("{speed=2}Hi{/speed}{w=1}{b}there{/b}", 10.0);
+        step(&mut app, 0.0);
+
+        // Two characters a second, then a one second pause before the rest
+        step(&mut app, 0.75);
+        assert_eq!(shown(&mut app), "H");
+        step(&mut app, 0.5);
+        assert_eq!(shown(&mut app), "Hi");
+        step(&mut app, 0.8);
+        assert_eq!(shown(&mut app), "Hi");
+        step(&mut app, 0.2);
+        assert_eq!(shown(&mut app), "Hith");
+
+        let mut query = app.world_mut().query::<(&DialogueSpan, &TextFont)>();
+        let bold = query.iter(app.world()).find(|(span, _)| span.text == "there").unwrap();
+        assert_eq!(bold.1.weight, FontWeight::BOLD);
     }
 }
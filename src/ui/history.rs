@@ -0,0 +1,169 @@
+use std::collections::VecDeque;
+
+use bevy::input::mouse::{AccumulatedMouseScroll, MouseScrollUnit};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::scene::characters::CharacterDefs;
+use crate::script::markup;
+use crate::ui::dialogue::{SPEAKER_COLOR, TEXT_COLOR};
+
+/// Default for `DialogueHistory::max_entries`.
+pub const DEFAULT_HISTORY_LENGTH: usize = 200;
+
+/// Pixels scrolled per line of mouse wheel movement.
+const SCROLL_LINE_HEIGHT: f32 = 32.0;
+
+/// A line of dialogue as it was shown.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub speaker: Option<String>,
+    /// ID of the defined character speaking, if any.
+    #[serde(default)]
+    pub character: Option<String>,
+    pub text: String,
+    /// Voice clip played with the line, relative to `audio/voice/`.
+    pub voice: Option<String>,
+}
+
+/// Every line shown so far, oldest first.
+#[derive(Resource)]
+pub struct DialogueHistory {
+    pub entries: VecDeque<HistoryEntry>,
+    /// Oldest entries are dropped past this many.
+    pub max_entries: usize,
+}
+
+impl Default for DialogueHistory {
+    fn default() -> Self {
+        Self {
+            entries: VecDeque::new(),
+            max_entries: DEFAULT_HISTORY_LENGTH,
+        }
+    }
+}
+
+impl DialogueHistory {
+    pub fn push(&mut self, entry: HistoryEntry) {
+        self.entries.push_back(entry);
+        while self.entries.len() > self.max_entries {
+            self.entries.pop_front();
+        }
+    }
+}
+
+/// Whether the history screen is showing.
+#[derive(Resource, Default)]
+pub struct HistoryScreen {
+    pub open: bool,
+}
+
+#[derive(Component)]
+pub struct HistoryRoot;
+
+/// H opens and closes the history, Escape closes it.
+pub fn toggle_history(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut screen: ResMut<HistoryScreen>,
+) {
+    if keyboard.just_pressed(KeyCode::KeyH) {
+        screen.open = !screen.open;
+    } else if keyboard.just_pressed(KeyCode::Escape) && screen.open {
+        screen.open = false;
+    }
+}
+
+pub fn history_ui_system(
+    mut commands: Commands,
+    screen: Res<HistoryScreen>,
+    history: Res<DialogueHistory>,
+    roots: Query<Entity, With<HistoryRoot>>,
+    asset_server: Res<AssetServer>,
+    character_defs: Res<CharacterDefs>,
+) {
+    if !screen.is_changed() {
+        return;
+    }
+
+    for root in &roots {
+        commands.entity(root).despawn();
+    }
+
+    if !screen.open {
+        return;
+    }
+
+    let font = asset_server.load("fonts/main.ttf");
+
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                position_type: PositionType::Absolute,
+                padding: UiRect::all(Val::Px(24.0)),
+                flex_direction: FlexDirection::Column,
+                overflow: Overflow::scroll_y(),
+                ..default()
+            },
+            // Start at the newest line; layout clamps this to the bottom
+            ScrollPosition(Vec2::new(0.0, f32::MAX)),
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.9)),
+            GlobalZIndex(10),
+            HistoryRoot,
+        ))
+        .with_children(|parent| {
+            for entry in &history.entries {
+                // Lines keep the colors the dialogue box showed them in
+                let def = entry.character.as_deref().and_then(|id| character_defs.get(id));
+                let name_color = def.and_then(|def| def.name_color).unwrap_or(SPEAKER_COLOR);
+                let text_color = def.and_then(|def| def.text_color).unwrap_or(TEXT_COLOR);
+
+                if let Some(speaker) = &entry.speaker {
+                    parent.spawn((
+                        Text::new(speaker.clone()),
+                        TextFont {
+                            font: font.clone(),
+                            font_size: 22.0,
+                            ..default()
+                        },
+                        TextColor(name_color),
+                    ));
+                }
+
+                parent.spawn((
+                    Text::new(markup::strip(&entry.text)),
+                    TextFont {
+                        font: font.clone(),
+                        font_size: 26.0,
+                        ..default()
+                    },
+                    TextColor(text_color),
+                    Node {
+                        margin: UiRect::bottom(Val::Px(12.0)),
+                        ..default()
+                    },
+                ));
+            }
+        });
+}
+
+/// Scrolls the open history with the mouse wheel.
+pub fn scroll_history(
+    scroll: Res<AccumulatedMouseScroll>,
+    mut panels: Query<(&mut ScrollPosition, &ComputedNode), With<HistoryRoot>>,
+) {
+    if scroll.delta.y == 0.0 {
+        return;
+    }
+
+    let delta = match scroll.unit {
+        MouseScrollUnit::Line => scroll.delta.y * SCROLL_LINE_HEIGHT,
+        MouseScrollUnit::Pixel => scroll.delta.y,
+    };
+
+    for (mut position, node) in &mut panels {
+        let max = (node.content_size.y - node.size().y).max(0.0) * node.inverse_scale_factor();
+        position.y = (position.y.min(max) - delta).clamp(0.0, max);
+    }
+}
@@ -1,2 +1,3 @@
 pub mod dialogue;
-pub mod choices;
\ No newline at end of file
+pub mod choices;
+pub mod history;
@@ -0,0 +1,29 @@
+use bevy::prelude::*;
+
+use crate::save::save_system::{read_save_file, write_save_file};
+use crate::vars::store::VarStore;
+
+/// File in the save directory holding the persistent variables.
+pub const PERSISTENT_FILE: &str = "persistent.ron";
+
+/// Reads the persistent variables left by earlier playthroughs.
+pub fn load_persistent_vars(mut vars: ResMut<VarStore>) {
+    match read_save_file(PERSISTENT_FILE) {
+        Ok(Some(persistent)) => vars.persistent = persistent,
+        // Nothing has been made persistent yet
+        Ok(None) => {}
+        Err(err) => error!("failed to read persistent variables: {}", err),
+    }
+}
+
+/// Writes the persistent variables out as soon as a script changes one.
+pub fn save_persistent_vars(mut vars: ResMut<VarStore>) {
+    if !vars.persistent_changed {
+        return;
+    }
+    vars.persistent_changed = false;
+
+    if let Err(err) = write_save_file(PERSISTENT_FILE, &vars.persistent) {
+        error!("failed to write persistent variables: {}", err);
+    }
+}
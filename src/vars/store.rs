@@ -1,7 +1,9 @@
 use std::collections::HashMap;
+use std::fmt;
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Value {
     Int(i64),
     Float(f32),
@@ -9,17 +11,68 @@ pub enum Value {
     Text(String),
 }
 
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::Bool(_) => "bool",
+            Value::Text(_) => "text",
+        }
+    }
+
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Int(v) => *v != 0,
+            Value::Float(v) => *v != 0.0,
+            Value::Bool(v) => *v,
+            Value::Text(v) => !v.is_empty(),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(v) => write!(f, "{}", v),
+            Value::Float(v) => write!(f, "{}", v),
+            Value::Bool(v) => write!(f, "{}", v),
+            Value::Text(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+/// Variables named with this prefix outlive a playthrough: they are kept in
+/// their own file rather than in saves.
+pub const PERSISTENT_PREFIX: &str = "persistent.";
+
 #[derive(Resource, Default)]
 pub struct VarStore {
+    /// Variables of the current game, stored in its saves.
     pub vars: HashMap<String, Value>,
+    /// Persistent variables, keyed without their prefix.
+    pub persistent: HashMap<String, Value>,
+    /// Set when a persistent variable changes and has yet to be written out.
+    pub persistent_changed: bool,
 }
 
 impl VarStore {
     pub fn set(&mut self, key: &str, value: Value) {
-        self.vars.insert(key.to_string(), value);
+        match key.strip_prefix(PERSISTENT_PREFIX) {
+            Some(name) => {
+                self.persistent.insert(name.to_string(), value);
+                self.persistent_changed = true;
+            }
+            None => {
+                self.vars.insert(key.to_string(), value);
+            }
+        }
     }
 
     pub fn get(&self, key: &str) -> Option<&Value> {
-        self.vars.get(key)
+        match key.strip_prefix(PERSISTENT_PREFIX) {
+            Some(name) => self.persistent.get(name),
+            None => self.vars.get(key),
+        }
     }
 }